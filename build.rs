@@ -1,6 +1,4 @@
-use peg;
 use serde_derive::{Deserialize, Serialize};
-use serde_yaml;
 use std::env;
 use std::fs;
 use std::io;
@@ -21,10 +19,10 @@ fn {} () {{
     let input = {:?};
     let target_source = {:?};
 
-    let result = parse(&input)
+    let result = parse(input)
         .expect(\"Parsing of the input for {} failed!\");
     eprintln!(\"{{}}\", &serde_yaml::to_string(&result).unwrap());
-    let target: ast::Element = serde_yaml::from_str(&target_source)
+    let target: ast::Element = serde_yaml::from_str(target_source)
         .expect(\"Parsing the documentation of {} failed!\");
     assert_eq!(&target, &result,
         \"comparing documentation (left) with parse result (right) failed!\");
@@ -39,7 +37,6 @@ macro_rules! TEST_HEADER {
 // THIS DOCUMENT IS AUTO-GENERATED AND SHOULD NOT BE EDITED BY HAND!
 
 use crate::ast;
-use serde_yaml;
 use crate::parse;
 
 "
@@ -80,17 +77,13 @@ fn generate_tests() {
         .join(Path::new("tests_generated.rs"))
         .with_extension("rs");
 
-    let mut in_file = fs::File::open(Path::new("doc/docs.yml"))
-        .ok()
-        .expect("Could not open input file!");
-    let mut out_file = fs::File::create(Path::new(&out_path))
-        .ok()
-        .expect("Could not open output file!");
+    let mut in_file =
+        fs::File::open(Path::new("doc/docs.yml")).expect("Could not open input file!");
+    let mut out_file = fs::File::create(Path::new(&out_path)).expect("Could not open output file!");
 
     let mut content = String::new();
     in_file
         .read_to_string(&mut content)
-        .ok()
         .expect("Could not read file!");
 
     let tests: Vec<Test> =
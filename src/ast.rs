@@ -1,8 +1,18 @@
-/// Data structures describing the parsed document.
+// Data structures describing the parsed document.
 
 #[cfg(feature = "no_position")]
-use serde::{Serialize, SerializeMap, Serializer};
+use serde::ser::SerializeMap;
+#[cfg(feature = "simple_text")]
+use serde::{Deserialize, Deserializer};
+#[cfg(any(feature = "no_position", feature = "simple_text"))]
+use serde::{Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 
 /**
  * Element types used in the abstract syntax tree (AST).
@@ -10,8 +20,9 @@ use serde_derive::{Deserialize, Serialize};
  * Each element must keep track of its position in the original
  * input document. After parsing, the document tree can be serialized by serde.
  */
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone)]
 #[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+#[cfg_attr(feature = "simple_text", serde(remote = "Self"))]
 pub enum Element {
     Document(Document),
     Heading(Heading),
@@ -20,6 +31,7 @@ pub enum Element {
     Paragraph(Paragraph),
     Template(Template),
     TemplateArgument(TemplateArgument),
+    Parameter(Parameter),
     InternalReference(InternalReference),
     ExternalReference(ExternalReference),
     ListItem(ListItem),
@@ -29,108 +41,166 @@ pub enum Element {
     TableCell(TableCell),
     Comment(Comment),
     HtmlTag(HtmlTag),
+    ExtensionTag(ExtensionTag),
     Gallery(Gallery),
+    Figure(Figure),
+    Code(Code),
+    Redirect(Redirect),
+    MagicWord(MagicWord),
+    Variable(Variable),
+    HorizontalRule(HorizontalRule),
     Error(Error),
 }
 
 /// The document root.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Document {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub content: Vec<Element>,
 }
 
 /// Headings make a hierarchical document structure.
 /// Headings of higher depths have other headings as parents.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Heading {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub depth: usize,
+    /// Number of `=` the heading starts with, `None` if the heading was not
+    /// written with `=` markers, e.g. if it was generated by a transformation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_markers: Option<usize>,
+    /// Number of `=` the heading ends with, `None` if not written with markers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_markers: Option<usize>,
     pub caption: Vec<Element>,
     pub content: Vec<Element>,
 }
 
 /// Simple text.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Text {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub text: String,
 }
 
 /// A formatting wrapper, usually around text.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Formatted {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub markup: MarkupType,
     pub content: Vec<Element>,
 }
 
 /// Paragraphs are separated by newlines in the input document.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Paragraph {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub content: Vec<Element>,
 }
 
 /// A mediawiki template.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Template {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub name: Vec<Element>,
     pub content: Vec<Element>,
 }
 
 /// Argument of a mediawiki template.
 /// Empty name indicate anonymous arguments.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct TemplateArgument {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub name: String,
     pub value: Vec<Element>,
 }
 
+/// A reference to a parameter of the enclosing template, like `{{{1}}}` or
+/// `{{{title|default}}}`. The default is used if the parameter is not given.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct Parameter {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub name: Vec<Element>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Vec<Element>>,
+}
+
 /// A reference to internal data, such as embedded files
 /// or other articles.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct InternalReference {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub target: Vec<Element>,
     pub options: Vec<Vec<Element>>,
     pub caption: Vec<Element>,
+    /// The sort key of a category assignment, like `Key` in `[[Category:Foo|Key]]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_key: Option<String>,
+    /// The click target of a file given by its `link=` option, like `Page` in
+    /// `[[File:x.png|link=Page]]`. It is empty if the link is disabled with `link=`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// The interwiki prefix of a link to another wiki, like `wikipedia` for `[[wikipedia:Foo]]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interwiki: Option<String>,
 }
 
 /// External reference, usually hyperlinks.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct ExternalReference {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub target: String,
     pub caption: Vec<Element>,
 }
 
 /// List item of a certain `ListItemKind`.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct ListItem {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub depth: usize,
     pub kind: ListItemKind,
     pub content: Vec<Element>,
@@ -138,21 +208,26 @@ pub struct ListItem {
 
 /// List of items. The `ListItemKind` of its children
 /// can be heterogenous.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct List {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub content: Vec<Element>,
 }
 
 /// A mediawiki table. `attributes` represent html
 /// attributes assigned to the table.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// If the table end is missing, the last row is an `Error`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Table {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub attributes: Vec<TagAttribute>,
     pub caption: Vec<Element>,
     pub caption_attributes: Vec<TagAttribute>,
@@ -161,11 +236,13 @@ pub struct Table {
 
 /// A table row. `attributes` represent html
 /// attributes assigned to the table.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct TableRow {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub attributes: Vec<TagAttribute>,
     pub cells: Vec<Element>,
 }
@@ -173,57 +250,174 @@ pub struct TableRow {
 /// A single table cell. `attributes` represent html
 /// attributes assigned to the table. `header` is true
 /// if this cell is marked as a header cell.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct TableCell {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub header: bool,
     pub attributes: Vec<TagAttribute>,
     pub content: Vec<Element>,
 }
 
 /// Comments in the input document.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Comment {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub text: String,
+    /// Is this a conditional comment for Internet Explorer, like `<!--[if IE]> ... <![endif]-->`?
+    /// Such comments may contain markup, so renderers usually drop them.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub conditional: bool,
+}
+
+impl Comment {
+    /// Is `text` the text of a conditional comment, like `[if IE]> ... <![endif]`?
+    /// The parts `<!--[if !IE]><!-->` and `<!--<![endif]-->` enclosing content
+    /// for other browsers are conditional comments as well.
+    pub fn is_conditional_text(text: &str) -> bool {
+        let text = text.trim().to_lowercase();
+        (text.starts_with("[if ") && text.contains("]>")) || text.starts_with("<![endif]")
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 /// Html tags not encoding formatting elements.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct HtmlTag {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub name: String,
     pub attributes: Vec<TagAttribute>,
     pub content: Vec<Element>,
 }
 
+/// An extension tag with a body in a domain-specific language,
+/// like `<score>` or `<timeline>`. The body is kept verbatim.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct ExtensionTag {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub name: String,
+    pub attributes: Vec<TagAttribute>,
+    pub body: String,
+}
+
 /// Gallery of images (or interal references in general).
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Gallery {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub attributes: Vec<TagAttribute>,
     pub content: Vec<Element>,
 }
 
+/// A table or image together with its caption, as created by
+/// `optional_transformations::wrap_figures`. The parser does not create figures.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct Figure {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub content: Vec<Element>,
+    pub caption: Vec<Element>,
+}
+
+/// Source code, as created by `optional_transformations::normalize_code`
+/// from `<code>`, `<pre>`, indented text and `<syntaxhighlight>`.
+/// The parser does not create code elements.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct Code {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// Inline code like `<code>`, otherwise a code block.
+    pub inline: bool,
+    /// The language of the code, like `rust` for `<syntaxhighlight lang="rust">`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub text: String,
+}
+
+/// A redirect to another page, only allowed at the document start.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct Redirect {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub target: Vec<Element>,
+}
+
+/// A behavior switch like `__NOTOC__`. `name` is the uppercase name without underscores.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct MagicWord {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub name: String,
+}
+
+/// A variable like `{{PAGENAME}}`, which is replaced by information about the page or site.
+/// Only names listed in `VARIABLES` are variables, others are templates.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct Variable {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub name: String,
+}
+
+/// A horizontal rule, written as a line of at least four dashes (`----`).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub struct HorizontalRule {
+    #[serde(default)]
+    pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+}
+
 /// Indicates an erroneous part of the document tree.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct Error {
     #[serde(default)]
     pub position: Span,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub message: String,
 }
 
 /// Types of markup a section of text may have.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum MarkupType {
     NoWiki,
@@ -238,9 +432,10 @@ pub enum MarkupType {
 }
 
 /// Types of markup a section of text may have.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ListItemKind {
+    #[default]
     Unordered,
     Definition,
     DefinitionTerm,
@@ -253,8 +448,12 @@ pub enum ListItemKind {
  * The `PartialEq` implementation allows for a "any" position (all zero), which is
  * equal to any other position. This is used to reduce clutter in tests, where
  * a default Position ("{}") can be used where the actual representation is irrelevant.
+ * For consistency with this equality, positions are not hashed: structurally equal
+ * elements have the same hash, regardless of their positions. As this equality is not
+ * transitive, elements do not implement `Eq`; use `transformations::StructuralKey`
+ * to put trees in a `HashSet` or `HashMap`.
  */
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(
     rename_all = "lowercase",
     default = "Position::any_position",
@@ -267,7 +466,7 @@ pub struct Position {
 }
 
 /// Holds position information (start and end) for one element
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Deserialize, PartialEq, Hash, Clone)]
 #[cfg_attr(not(feature = "no_position"), derive(Serialize))]
 #[serde(rename_all = "lowercase", default = "Span::any", deny_unknown_fields)]
 pub struct Span {
@@ -276,13 +475,19 @@ pub struct Span {
 }
 
 /// Represents a pair of html tag attribute and value.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+///
+/// In tables, a template may provide attributes, like `{| {{table style}}`. Such an attribute
+/// has an empty key, its source text as value and the parsed template in `value_elements`.
+/// Transformations and traversions visit these elements like children of the table element.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone, Default)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct TagAttribute {
     #[serde(default)]
     pub position: Span,
     pub key: String,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub value_elements: Vec<Element>,
 }
 
 /// Position of a source line of code.
@@ -303,20 +508,63 @@ impl<'input> SourceLine<'input> {
         }
         false
     }
+
+    /// returns the line containing `pos`.
+    pub fn containing<'s>(pos: usize, slocs: &'s [SourceLine<'input>]) -> Option<&'s Self> {
+        let index = slocs.partition_point(|sloc| sloc.end <= pos);
+        slocs.get(index).filter(|sloc| pos >= sloc.start)
+    }
+
+    /// returns the source text from `start` to `end`, which may span several lines.
+    pub fn slice(start: usize, end: usize, slocs: &[SourceLine]) -> String {
+        let mut text = String::new();
+        for sloc in slocs.iter().filter(|s| s.end > start && s.start < end) {
+            let from = start.saturating_sub(sloc.start);
+            let to = end - sloc.start;
+            let content_end = to.min(sloc.content.len());
+            if from < content_end {
+                text.push_str(&sloc.content[from..content_end]);
+            }
+            if to > sloc.content.len() {
+                text.push('\n');
+            }
+        }
+        text
+    }
 }
 
 impl MarkupType {
-    /// Match an HTML tag name to it's markup type.
-    pub fn by_tag_name(tag: &str) -> MarkupType {
-        match &tag.to_lowercase()[..] {
-            "math" => MarkupType::Math,
-            "del" | "s" => MarkupType::StrikeThrough,
-            "nowiki" => MarkupType::NoWiki,
-            "u" | "ins" => MarkupType::Underline,
-            "code" => MarkupType::Code,
-            "blockquote" => MarkupType::Blockquote,
-            "pre" => MarkupType::Preformatted,
-            _ => panic!("markup type lookup not implemented for {}!", tag),
+    /// Match an HTML tag name to it's markup type, `None` for other tags.
+    pub fn by_tag_name(tag: &str) -> Option<MarkupType> {
+        MARKUP_TAGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(tag))
+            .map(|(_, markup)| *markup)
+    }
+
+    /// Like `by_tag_name`, but returns the unknown tag name as error.
+    pub fn try_by_tag_name(tag: &str) -> Result<MarkupType, String> {
+        MarkupType::by_tag_name(tag).ok_or_else(|| tag.to_string())
+    }
+
+    /// Markup types which form a block of their own, rather than formatting inline content.
+    pub fn is_block(self) -> bool {
+        matches!(self, MarkupType::Blockquote | MarkupType::Preformatted)
+    }
+}
+
+/// Characters starting a list item, e.g. `*` in `* item`.
+pub const LIST_MARKERS: [char; 4] = ['*', '#', ':', ';'];
+
+impl ListItemKind {
+    /// Get the kind of list item a marker character (see `LIST_MARKERS`) starts.
+    pub fn from_marker(marker: char) -> Option<ListItemKind> {
+        match marker {
+            '*' => Some(ListItemKind::Unordered),
+            '#' => Some(ListItemKind::Ordered),
+            ':' => Some(ListItemKind::Definition),
+            ';' => Some(ListItemKind::DefinitionTerm),
+            _ => None,
         }
     }
 }
@@ -332,6 +580,7 @@ impl Element {
             Element::Paragraph(ref e) => &e.position,
             Element::Template(ref e) => &e.position,
             Element::TemplateArgument(ref e) => &e.position,
+            Element::Parameter(ref e) => &e.position,
             Element::InternalReference(ref e) => &e.position,
             Element::ExternalReference(ref e) => &e.position,
             Element::List(ref e) => &e.position,
@@ -341,7 +590,14 @@ impl Element {
             Element::TableCell(ref e) => &e.position,
             Element::Comment(ref e) => &e.position,
             Element::HtmlTag(ref e) => &e.position,
+            Element::ExtensionTag(ref e) => &e.position,
             Element::Gallery(ref e) => &e.position,
+            Element::Figure(ref e) => &e.position,
+            Element::Code(ref e) => &e.position,
+            Element::Redirect(ref e) => &e.position,
+            Element::MagicWord(ref e) => &e.position,
+            Element::Variable(ref e) => &e.position,
+            Element::HorizontalRule(ref e) => &e.position,
             Element::Error(ref e) => &e.position,
         }
     }
@@ -356,6 +612,7 @@ impl Element {
             Element::Paragraph(ref mut e) => &mut e.position,
             Element::Template(ref mut e) => &mut e.position,
             Element::TemplateArgument(ref mut e) => &mut e.position,
+            Element::Parameter(ref mut e) => &mut e.position,
             Element::InternalReference(ref mut e) => &mut e.position,
             Element::ExternalReference(ref mut e) => &mut e.position,
             Element::List(ref mut e) => &mut e.position,
@@ -365,11 +622,82 @@ impl Element {
             Element::TableCell(ref mut e) => &mut e.position,
             Element::Comment(ref mut e) => &mut e.position,
             Element::HtmlTag(ref mut e) => &mut e.position,
+            Element::ExtensionTag(ref mut e) => &mut e.position,
             Element::Gallery(ref mut e) => &mut e.position,
+            Element::Figure(ref mut e) => &mut e.position,
+            Element::Code(ref mut e) => &mut e.position,
+            Element::Redirect(ref mut e) => &mut e.position,
+            Element::MagicWord(ref mut e) => &mut e.position,
+            Element::Variable(ref mut e) => &mut e.position,
+            Element::HorizontalRule(ref mut e) => &mut e.position,
             Element::Error(ref mut e) => &mut e.position,
         }
     }
 
+    /// returns the id assigned to an element, if any.
+    pub fn get_id(&self) -> Option<u64> {
+        match *self {
+            Element::Document(ref e) => e.id,
+            Element::Heading(ref e) => e.id,
+            Element::Text(ref e) => e.id,
+            Element::Formatted(ref e) => e.id,
+            Element::Paragraph(ref e) => e.id,
+            Element::Template(ref e) => e.id,
+            Element::TemplateArgument(ref e) => e.id,
+            Element::Parameter(ref e) => e.id,
+            Element::InternalReference(ref e) => e.id,
+            Element::ExternalReference(ref e) => e.id,
+            Element::ListItem(ref e) => e.id,
+            Element::List(ref e) => e.id,
+            Element::Table(ref e) => e.id,
+            Element::TableRow(ref e) => e.id,
+            Element::TableCell(ref e) => e.id,
+            Element::Comment(ref e) => e.id,
+            Element::HtmlTag(ref e) => e.id,
+            Element::ExtensionTag(ref e) => e.id,
+            Element::Gallery(ref e) => e.id,
+            Element::Figure(ref e) => e.id,
+            Element::Code(ref e) => e.id,
+            Element::Redirect(ref e) => e.id,
+            Element::MagicWord(ref e) => e.id,
+            Element::Variable(ref e) => e.id,
+            Element::HorizontalRule(ref e) => e.id,
+            Element::Error(ref e) => e.id,
+        }
+    }
+
+    /// returns a mutable reference to the id of an element.
+    pub fn get_id_mut(&mut self) -> &mut Option<u64> {
+        match *self {
+            Element::Document(ref mut e) => &mut e.id,
+            Element::Heading(ref mut e) => &mut e.id,
+            Element::Text(ref mut e) => &mut e.id,
+            Element::Formatted(ref mut e) => &mut e.id,
+            Element::Paragraph(ref mut e) => &mut e.id,
+            Element::Template(ref mut e) => &mut e.id,
+            Element::TemplateArgument(ref mut e) => &mut e.id,
+            Element::Parameter(ref mut e) => &mut e.id,
+            Element::InternalReference(ref mut e) => &mut e.id,
+            Element::ExternalReference(ref mut e) => &mut e.id,
+            Element::ListItem(ref mut e) => &mut e.id,
+            Element::List(ref mut e) => &mut e.id,
+            Element::Table(ref mut e) => &mut e.id,
+            Element::TableRow(ref mut e) => &mut e.id,
+            Element::TableCell(ref mut e) => &mut e.id,
+            Element::Comment(ref mut e) => &mut e.id,
+            Element::HtmlTag(ref mut e) => &mut e.id,
+            Element::ExtensionTag(ref mut e) => &mut e.id,
+            Element::Gallery(ref mut e) => &mut e.id,
+            Element::Figure(ref mut e) => &mut e.id,
+            Element::Code(ref mut e) => &mut e.id,
+            Element::Redirect(ref mut e) => &mut e.id,
+            Element::MagicWord(ref mut e) => &mut e.id,
+            Element::Variable(ref mut e) => &mut e.id,
+            Element::HorizontalRule(ref mut e) => &mut e.id,
+            Element::Error(ref mut e) => &mut e.id,
+        }
+    }
+
     /// returns the variant name of an element.
     pub fn get_variant_name(&self) -> &str {
         match *self {
@@ -380,6 +708,7 @@ impl Element {
             Element::Paragraph(_) => "Paragraph",
             Element::Template(_) => "Template",
             Element::TemplateArgument(_) => "TemplateArgument",
+            Element::Parameter(_) => "Parameter",
             Element::InternalReference(_) => "InternalReference",
             Element::ExternalReference(_) => "ExternalReference",
             Element::List(_) => "List",
@@ -389,10 +718,782 @@ impl Element {
             Element::TableCell(_) => "TableCell",
             Element::Comment(_) => "Comment",
             Element::HtmlTag(_) => "HtmlTag",
+            Element::ExtensionTag(_) => "ExtensionTag",
             Element::Gallery(_) => "Gallery",
+            Element::Figure(_) => "Figure",
+            Element::Code(_) => "Code",
+            Element::Redirect(_) => "Redirect",
+            Element::MagicWord(_) => "MagicWord",
+            Element::Variable(_) => "Variable",
+            Element::HorizontalRule(_) => "HorizontalRule",
             Element::Error(_) => "Error",
         }
     }
+
+    /// returns true if this element is a block element, like headings,
+    /// paragraphs, lists, tables and galleries.
+    ///
+    /// Structural children like list items, table rows / cells or template arguments
+    /// are neither block nor inline elements.
+    pub fn is_block(&self) -> bool {
+        match *self {
+            Element::Heading(_)
+            | Element::Paragraph(_)
+            | Element::List(_)
+            | Element::Table(_)
+            | Element::Gallery(_)
+            | Element::Figure(_)
+            | Element::Redirect(_)
+            | Element::HorizontalRule(_) => true,
+            Element::Code(ref e) => !e.inline,
+            Element::Formatted(ref e) => e.markup.is_block(),
+            _ => false,
+        }
+    }
+
+    /// returns true if this element is an inline element, like text,
+    /// formatted text, references or templates.
+    pub fn is_inline(&self) -> bool {
+        match *self {
+            Element::Text(_)
+            | Element::Template(_)
+            | Element::Parameter(_)
+            | Element::InternalReference(_)
+            | Element::ExternalReference(_)
+            | Element::Comment(_)
+            | Element::HtmlTag(_)
+            | Element::ExtensionTag(_)
+            | Element::MagicWord(_)
+            | Element::Variable(_) => true,
+            Element::Code(ref e) => e.inline,
+            Element::Formatted(ref e) => !e.markup.is_block(),
+            _ => false,
+        }
+    }
+}
+
+/// Error of converting an element into a variant struct it does not contain.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VariantError {
+    /// The requested variant, e.g. `Template`.
+    pub expected: &'static str,
+    /// The actual variant of the element.
+    pub found: String,
+}
+
+impl fmt::Display for VariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "expected a {} element, found {}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for VariantError {}
+
+/// Implements `TryFrom<Element>` and a reference accessor for every variant struct.
+macro_rules! variant_conversions {
+    ($($variant:ident => $accessor:ident),*) => {
+        $(
+            impl TryFrom<Element> for $variant {
+                type Error = VariantError;
+
+                fn try_from(elem: Element) -> Result<Self, Self::Error> {
+                    match elem {
+                        Element::$variant(e) => Ok(e),
+                        other => Err(VariantError {
+                            expected: stringify!($variant),
+                            found: other.get_variant_name().to_string(),
+                        }),
+                    }
+                }
+            }
+        )*
+
+        impl Element {
+            $(
+                #[doc = concat!("returns the inner `", stringify!($variant), "` if this is one.")]
+                pub fn $accessor(&self) -> Option<&$variant> {
+                    match *self {
+                        Element::$variant(ref e) => Some(e),
+                        _ => None,
+                    }
+                }
+            )*
+        }
+    };
+}
+
+variant_conversions!(
+    Document => as_document,
+    Heading => as_heading,
+    Text => as_text,
+    Formatted => as_formatted,
+    Paragraph => as_paragraph,
+    Template => as_template,
+    TemplateArgument => as_template_argument,
+    Parameter => as_parameter,
+    InternalReference => as_internal_reference,
+    ExternalReference => as_external_reference,
+    ListItem => as_list_item,
+    List => as_list,
+    Table => as_table,
+    TableRow => as_table_row,
+    TableCell => as_table_cell,
+    Comment => as_comment,
+    HtmlTag => as_html_tag,
+    ExtensionTag => as_extension_tag,
+    Gallery => as_gallery,
+    Figure => as_figure,
+    Code => as_code,
+    Redirect => as_redirect,
+    MagicWord => as_magic_word,
+    Variable => as_variable,
+    HorizontalRule => as_horizontal_rule,
+    Error => as_error
+);
+
+/// Iterate over the top-level elements of a document, like `for elem in &doc`.
+impl<'a> IntoIterator for &'a Document {
+    type Item = &'a Element;
+    type IntoIter = std::slice::Iter<'a, Element>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.content.iter()
+    }
+}
+
+/// Canonical names of the MediaWiki namespaces.
+pub const NAMESPACES: [&str; 18] = [
+    "Media",
+    "Special",
+    "Talk",
+    "User",
+    "User talk",
+    "Project",
+    "Project talk",
+    "File",
+    "File talk",
+    "MediaWiki",
+    "MediaWiki talk",
+    "Template",
+    "Template talk",
+    "Help",
+    "Help talk",
+    "Category",
+    "Category talk",
+    "Module",
+];
+
+/// Legacy namespace names and the namespace they refer to.
+pub const NAMESPACE_ALIASES: [(&str, &str); 2] = [("Image", "File"), ("Image talk", "File talk")];
+
+impl InternalReference {
+    /// returns the namespace prefix of the target as written, e.g. `Image` for `[[Image:x.png]]`.
+    /// Only known namespaces (see `NAMESPACES`) and their aliases are considered.
+    pub fn raw_namespace(&self) -> Option<&str> {
+        let text = match self.target.first() {
+            Some(Element::Text(ref t)) => t.text.trim_start().trim_start_matches(':'),
+            _ => return None,
+        };
+        let prefix = text.split(':').next()?;
+        if text.len() > prefix.len() && canonical_namespace(prefix).is_some() {
+            Some(prefix.trim())
+        } else {
+            None
+        }
+    }
+
+    /// returns the canonical namespace of the target, e.g. `File` for `[[Image:x.png]]`.
+    pub fn namespace(&self) -> Option<&'static str> {
+        self.raw_namespace().and_then(canonical_namespace)
+    }
+
+    /// returns the linked page without section anchor and leading colon,
+    /// e.g. `Page` for `[[Page#Section]]`. It is empty for links within the same page.
+    ///
+    /// ```
+    /// use mediawiki_parser::{parse, Element};
+    ///
+    /// let root = parse("[[Page#Some section|text]] [[#Top]]").unwrap();
+    /// let links: Vec<_> = match root {
+    ///     Element::Document(ref doc) => doc.content[0]
+    ///         .as_paragraph()
+    ///         .unwrap()
+    ///         .content
+    ///         .iter()
+    ///         .filter_map(|e| e.as_internal_reference())
+    ///         .map(|link| (link.page(), link.anchor()))
+    ///         .collect(),
+    ///     _ => unreachable!(),
+    /// };
+    /// assert_eq!(
+    ///     links,
+    ///     vec![
+    ///         ("Page".to_string(), Some("Some section".to_string())),
+    ///         (String::new(), Some("Top".to_string())),
+    ///     ]
+    /// );
+    /// ```
+    pub fn page(&self) -> String {
+        let target = self.target_text();
+        let page = target.split('#').next().unwrap_or_default();
+        page.trim().trim_start_matches(':').trim().to_string()
+    }
+
+    /// returns the section anchor of the target, e.g. `Section` for `[[Page#Section]]`.
+    pub fn anchor(&self) -> Option<String> {
+        let target = self.target_text();
+        let (_, anchor) = target.split_once('#')?;
+        Some(anchor.trim().to_string())
+    }
+
+    /// the text of the target, ignoring other elements like templates.
+    fn target_text(&self) -> String {
+        self.target
+            .iter()
+            .filter_map(|e| match *e {
+                Element::Text(ref t) => Some(t.text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Names of known parser functions. They are called like templates,
+/// with arguments after a colon, e.g. `{{#if: x | y}}` or `{{ns:0}}`.
+pub const PARSER_FUNCTIONS: [&str; 39] = [
+    "#if",
+    "#ifeq",
+    "#iferror",
+    "#ifexist",
+    "#ifexpr",
+    "#switch",
+    "#expr",
+    "#time",
+    "#timel",
+    "#rel2abs",
+    "#titleparts",
+    "#invoke",
+    "#tag",
+    "#language",
+    "#special",
+    "#speciale",
+    "#formatdate",
+    "#property",
+    "ns",
+    "nse",
+    "uc",
+    "lc",
+    "ucfirst",
+    "lcfirst",
+    "urlencode",
+    "anchorencode",
+    "fullurl",
+    "localurl",
+    "canonicalurl",
+    "filepath",
+    "formatnum",
+    "padleft",
+    "padright",
+    "plural",
+    "grammar",
+    "gender",
+    "int",
+    "displaytitle",
+    "defaultsort",
+];
+
+/// Names of variables like `{{PAGENAME}}`, which are case-sensitive.
+pub const VARIABLES: [&str; 81] = [
+    "CURRENTYEAR",
+    "CURRENTMONTH",
+    "CURRENTMONTH1",
+    "CURRENTMONTHNAME",
+    "CURRENTMONTHNAMEGEN",
+    "CURRENTMONTHABBREV",
+    "CURRENTDAY",
+    "CURRENTDAY2",
+    "CURRENTDOW",
+    "CURRENTDAYNAME",
+    "CURRENTTIME",
+    "CURRENTHOUR",
+    "CURRENTWEEK",
+    "CURRENTTIMESTAMP",
+    "LOCALYEAR",
+    "LOCALMONTH",
+    "LOCALMONTH1",
+    "LOCALMONTHNAME",
+    "LOCALMONTHNAMEGEN",
+    "LOCALMONTHABBREV",
+    "LOCALDAY",
+    "LOCALDAY2",
+    "LOCALDOW",
+    "LOCALDAYNAME",
+    "LOCALTIME",
+    "LOCALHOUR",
+    "LOCALWEEK",
+    "LOCALTIMESTAMP",
+    "SITENAME",
+    "SERVER",
+    "SERVERNAME",
+    "SCRIPTPATH",
+    "STYLEPATH",
+    "CURRENTVERSION",
+    "CONTENTLANGUAGE",
+    "CONTENTLANG",
+    "PAGEID",
+    "PAGELANGUAGE",
+    "REVISIONID",
+    "REVISIONDAY",
+    "REVISIONDAY2",
+    "REVISIONMONTH",
+    "REVISIONMONTH1",
+    "REVISIONYEAR",
+    "REVISIONTIMESTAMP",
+    "REVISIONUSER",
+    "REVISIONSIZE",
+    "NUMBEROFPAGES",
+    "NUMBEROFARTICLES",
+    "NUMBEROFFILES",
+    "NUMBEROFEDITS",
+    "NUMBEROFUSERS",
+    "NUMBEROFADMINS",
+    "NUMBEROFACTIVEUSERS",
+    "FULLPAGENAME",
+    "FULLPAGENAMEE",
+    "PAGENAME",
+    "PAGENAMEE",
+    "BASEPAGENAME",
+    "BASEPAGENAMEE",
+    "ROOTPAGENAME",
+    "ROOTPAGENAMEE",
+    "SUBPAGENAME",
+    "SUBPAGENAMEE",
+    "SUBJECTPAGENAME",
+    "SUBJECTPAGENAMEE",
+    "ARTICLEPAGENAME",
+    "ARTICLEPAGENAMEE",
+    "TALKPAGENAME",
+    "TALKPAGENAMEE",
+    "NAMESPACE",
+    "NAMESPACEE",
+    "NAMESPACENUMBER",
+    "TALKSPACE",
+    "TALKSPACEE",
+    "SUBJECTSPACE",
+    "SUBJECTSPACEE",
+    "ARTICLESPACE",
+    "ARTICLESPACEE",
+    "DIRMARK",
+    "DIRECTIONMARK",
+];
+
+/// Html tags which are allowed in wiki text, in lowercase.
+pub const HTML_TAGS: [&str; 59] = [
+    "b",
+    "bdi",
+    "bdo",
+    "del",
+    "i",
+    "ins",
+    "u",
+    "font",
+    "big",
+    "small",
+    "sub",
+    "sup",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "cite",
+    "code",
+    "em",
+    "s",
+    "strike",
+    "strong",
+    "tt",
+    "var",
+    "div",
+    "center",
+    "blockquote",
+    "ol",
+    "ul",
+    "dl",
+    "table",
+    "caption",
+    "pre",
+    "ruby",
+    "rb",
+    "rp",
+    "rt",
+    "rtc",
+    "p",
+    "span",
+    "abbr",
+    "dfn",
+    "kbd",
+    "samp",
+    "data",
+    "time",
+    "mark",
+    "br",
+    "wbr",
+    "hr",
+    "li",
+    "dt",
+    "dd",
+    "td",
+    "th",
+    "tr",
+    "q",
+];
+
+/// Tags of common MediaWiki extensions, like `<ref>` or `<gallery>`, in lowercase.
+///
+/// Includes the extension tags the grammar parses with rules of their own, like
+/// `<math>` and `<nowiki>` (see `MARKUP_TAGS`) or `<gallery>`, and the default
+/// `raw_extension_tags` of `GeneralSettings`.
+pub const EXTENSION_TAGS: [&str; 28] = [
+    "ref",
+    "references",
+    "nowiki",
+    "math",
+    "gallery",
+    "pre",
+    "poem",
+    "score",
+    "timeline",
+    "syntaxhighlight",
+    "source",
+    "includeonly",
+    "noinclude",
+    "onlyinclude",
+    "templatedata",
+    "templatestyles",
+    "indicator",
+    "chem",
+    "ce",
+    "graph",
+    "categorytree",
+    "imagemap",
+    "inputbox",
+    "section",
+    "hiero",
+    "mapframe",
+    "maplink",
+    "charinsert",
+];
+
+/// Html tags which the parser recognizes, see `HTML_TAGS`. Other tags are kept
+/// as `HtmlTag` or reported as errors, depending on `unknown_tags_as_errors`.
+pub fn known_tags() -> &'static [&'static str] {
+    &HTML_TAGS
+}
+
+/// Extension tags which the parser recognizes with the default settings, see
+/// `EXTENSION_TAGS`. `GeneralSettings::known_extension_tags` also includes the
+/// configured raw extension tags.
+pub fn known_extension_tags() -> &'static [&'static str] {
+    &EXTENSION_TAGS
+}
+
+/// Tags which are parsed as `Formatted` with a certain `MarkupType`.
+pub const MARKUP_TAGS: [(&str, MarkupType); 9] = [
+    ("math", MarkupType::Math),
+    ("del", MarkupType::StrikeThrough),
+    ("s", MarkupType::StrikeThrough),
+    ("nowiki", MarkupType::NoWiki),
+    ("u", MarkupType::Underline),
+    ("ins", MarkupType::Underline),
+    ("code", MarkupType::Code),
+    ("blockquote", MarkupType::Blockquote),
+    ("pre", MarkupType::Preformatted),
+];
+
+impl Template {
+    /// returns the parser function this template calls, e.g. `#if` for `{{#if: x | y}}`.
+    ///
+    /// Only functions registered in `PARSER_FUNCTIONS` are recognized, so templates with
+    /// a namespace-qualified name, like `{{Template:Foo}}`, are still transclusions.
+    ///
+    /// ```
+    /// use mediawiki_parser::{parse, Element};
+    ///
+    /// let root = parse("{{ns:0}} {{Template:Foo}}").unwrap();
+    /// let paragraph = match root {
+    ///     Element::Document(ref doc) => doc.content[0].clone(),
+    ///     _ => unreachable!(),
+    /// };
+    /// let functions: Vec<_> = match paragraph {
+    ///     Element::Paragraph(ref par) => par
+    ///         .content
+    ///         .iter()
+    ///         .filter_map(|e| match *e {
+    ///             Element::Template(ref t) => Some(t.parser_function()),
+    ///             _ => None,
+    ///         })
+    ///         .collect(),
+    ///     _ => unreachable!(),
+    /// };
+    /// assert_eq!(functions, vec![Some("ns"), None]);
+    /// ```
+    pub fn parser_function(&self) -> Option<&'static str> {
+        let text = match self.name.first() {
+            Some(Element::Text(ref t)) => t.text.trim_start(),
+            _ => return None,
+        };
+        let prefix = text.split(':').next()?;
+        if text.len() == prefix.len() {
+            return None;
+        }
+        PARSER_FUNCTIONS
+            .iter()
+            .cloned()
+            .find(|f| f.eq_ignore_ascii_case(prefix.trim()))
+    }
+
+    /// checks if this template has the given name, following mediawiki's title rules:
+    /// the first letter is case-insensitive, underscores equal spaces and
+    /// whitespace is collapsed. The `Template:` namespace prefix is optional.
+    ///
+    /// Parser functions and templates with dynamic names never match.
+    pub fn name_matches(&self, other: &str) -> bool {
+        if self.parser_function().is_some() {
+            return false;
+        }
+        let name = match self.name_str() {
+            Some(name) => normalize_template_title(&name),
+            None => return false,
+        };
+        !name.is_empty() && name == normalize_template_title(other)
+    }
+
+    /// returns the name of this template as written, e.g. `Infobox` for `{{ Infobox |x}}`,
+    /// or `None` if the name is dynamic like in `{{ {{foo}}bar }}`.
+    pub fn name_str(&self) -> Option<String> {
+        let mut name = String::new();
+        for elem in &self.name {
+            match *elem {
+                Element::Text(ref t) => name.push_str(&t.text),
+                _ => return None,
+            }
+        }
+        Some(name.trim().to_string())
+    }
+
+    /// returns the argument with the given name, e.g. `"1"` for the first anonymous argument.
+    pub fn get_arg(&self, name: &str) -> Option<&TemplateArgument> {
+        self.content.iter().find_map(|e| match *e {
+            Element::TemplateArgument(ref arg) if arg.name.trim() == name.trim() => Some(arg),
+            _ => None,
+        })
+    }
+}
+
+impl Table {
+    /// returns the space-separated classes of the `class` attribute,
+    /// e.g. `wikitable` and `sortable` for `{| class="wikitable sortable"`.
+    pub fn classes(&self) -> Vec<&str> {
+        self.attributes
+            .iter()
+            .filter(|attr| attr.key.trim().eq_ignore_ascii_case("class"))
+            .flat_map(|attr| attr.value.split_whitespace())
+            .collect()
+    }
+
+    /// Is `class` one of the classes of this table?
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes().contains(&class)
+    }
+
+    /// returns the value of an attribute of the table, e.g. `1` for `border` in `{| border=1`.
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        find_attribute(&self.attributes, key)
+    }
+
+    /// returns the declarations of the `style` attribute of the table by property.
+    pub fn style_map(&self) -> BTreeMap<String, String> {
+        style_declarations(&self.attributes)
+    }
+
+    /// returns the horizontal alignment given by `text-align` in `style` or by `align`.
+    pub fn horizontal_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "text-align", "align")
+    }
+
+    /// returns the vertical alignment given by `vertical-align` in `style` or by `valign`.
+    pub fn vertical_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "vertical-align", "valign")
+    }
+}
+
+impl TableRow {
+    /// returns the value of an attribute of the row, e.g. `x` for `class` in `|- class=x`.
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        find_attribute(&self.attributes, key)
+    }
+
+    /// returns the declarations of the `style` attribute of the row by property.
+    pub fn style_map(&self) -> BTreeMap<String, String> {
+        style_declarations(&self.attributes)
+    }
+
+    /// returns the horizontal alignment given by `text-align` in `style` or by `align`.
+    pub fn horizontal_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "text-align", "align")
+    }
+
+    /// returns the vertical alignment given by `vertical-align` in `style` or by `valign`.
+    pub fn vertical_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "vertical-align", "valign")
+    }
+}
+
+impl TableCell {
+    /// returns the value of an attribute of the cell, e.g. `2` for `colspan` in `| colspan=2 | x`.
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        find_attribute(&self.attributes, key)
+    }
+
+    /// returns the declarations of the `style` attribute of the cell by property.
+    pub fn style_map(&self) -> BTreeMap<String, String> {
+        style_declarations(&self.attributes)
+    }
+
+    /// returns the horizontal alignment given by `text-align` in `style` or by `align`.
+    pub fn horizontal_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "text-align", "align")
+    }
+
+    /// returns the vertical alignment given by `vertical-align` in `style` or by `valign`.
+    pub fn vertical_align(&self) -> Option<Align> {
+        alignment(&self.attributes, "vertical-align", "valign")
+    }
+}
+
+/// Alignment of a table, row or cell.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+    Justify,
+    Top,
+    Middle,
+    Bottom,
+    Baseline,
+}
+
+impl Align {
+    /// Parse an alignment value like `center`, case-insensitive.
+    pub fn from_value(value: &str) -> Option<Align> {
+        Some(match value.trim().to_lowercase().as_str() {
+            "left" => Align::Left,
+            "center" => Align::Center,
+            "right" => Align::Right,
+            "justify" => Align::Justify,
+            "top" => Align::Top,
+            "middle" => Align::Middle,
+            "bottom" => Align::Bottom,
+            "baseline" => Align::Baseline,
+            _ => return None,
+        })
+    }
+}
+
+/// Get an alignment from a css property of the `style` attribute,
+/// or else from a legacy html attribute like `align`.
+fn alignment(attributes: &[TagAttribute], property: &str, legacy: &str) -> Option<Align> {
+    style_declarations(attributes)
+        .get(property)
+        .and_then(|value| Align::from_value(value))
+        .or_else(|| find_attribute(attributes, legacy).and_then(Align::from_value))
+}
+
+/// Get the value of an attribute, the key is case-insensitive.
+fn find_attribute<'a>(attributes: &'a [TagAttribute], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attr| attr.key.trim().eq_ignore_ascii_case(key))
+        .map(|attr| attr.value.trim())
+}
+
+/// Split the `style` attribute into css declarations like `color: red`.
+/// Properties are lowercase, later declarations override earlier ones.
+fn style_declarations(attributes: &[TagAttribute]) -> BTreeMap<String, String> {
+    find_attribute(attributes, "style")
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|decl| {
+            let (property, value) = decl.split_once(':')?;
+            let property = property.trim().to_lowercase();
+            if property.is_empty() {
+                return None;
+            }
+            Some((property, value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Normalize a page title, so that titles of the same page are equal.
+///
+/// A leading colon and a `#fragment` are removed, underscores are equivalent to spaces,
+/// namespaces get their canonical name and the first letter of the page name is uppercase.
+/// For example, `:image:some_file.png#top` becomes `File:Some file.png`.
+pub fn normalize_title(title: &str) -> String {
+    let title = title.split('#').next().unwrap_or_default();
+    let title = title.replace('_', " ");
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = title.trim_start_matches(':').trim();
+
+    let ucfirst = |name: &str| {
+        let mut chars = name.trim().chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    };
+    match title.split_once(':') {
+        Some((prefix, name)) => match canonical_namespace(prefix) {
+            Some(namespace) => format!("{}:{}", namespace, ucfirst(name)),
+            None => ucfirst(title),
+        },
+        None => ucfirst(title),
+    }
+}
+
+/// Normalize the name of a template like `normalize_title`, without the optional
+/// `Template:` prefix. For example, `template:foo_bar` becomes `Foo bar`.
+pub fn normalize_template_title(title: &str) -> String {
+    let title = normalize_title(title);
+    match title.strip_prefix("Template:") {
+        Some(name) => normalize_title(name),
+        None => title,
+    }
+}
+
+/// Find the canonical name of a namespace name or alias.
+/// Namespace names are case-insensitive and underscores are equivalent to spaces.
+fn canonical_namespace(name: &str) -> Option<&'static str> {
+    let normalized = name.trim().replace('_', " ");
+    let matches = |candidate: &str| candidate.eq_ignore_ascii_case(&normalized);
+    NAMESPACES
+        .iter()
+        .cloned()
+        .find(|ns| matches(ns))
+        .or_else(|| {
+            NAMESPACE_ALIASES
+                .iter()
+                .find(|alias| matches(alias.0))
+                .map(|alias| alias.1)
+        })
 }
 
 impl Position {
@@ -420,6 +1521,11 @@ impl Position {
             col: 0,
         }
     }
+
+    /// checks if this is the "any" position, which is equal to all positions.
+    pub fn is_any(&self) -> bool {
+        self.offset == 0 && self.line == 0 && self.col == 0
+    }
 }
 
 impl Span {
@@ -436,6 +1542,44 @@ impl Span {
             end: Position::new(posr, source_lines),
         }
     }
+
+    /// returns the smallest span containing both spans.
+    /// "any" positions are ignored, unless both spans have them.
+    pub fn merge(&self, other: &Span) -> Span {
+        let pick = |a: &Position, b: &Position, first: bool| {
+            if a.is_any() || (!b.is_any() && (b.offset < a.offset) == first) {
+                b.clone()
+            } else {
+                a.clone()
+            }
+        };
+        Span {
+            start: pick(&self.start, &other.start, true),
+            end: pick(&self.end, &other.end, false),
+        }
+    }
+
+    /// returns the smallest span containing all of the given elements.
+    pub fn enclosing(elems: &[Element]) -> Span {
+        elems
+            .iter()
+            .fold(Span::any(), |span, e| span.merge(e.get_position()))
+    }
+
+    /// returns the byte range of this span in the input, e.g. to slice the source text.
+    /// An "any" span has the empty range `0..0`.
+    pub fn as_range(&self) -> Range<usize> {
+        if self.start.is_any() || self.end.is_any() {
+            return 0..0;
+        }
+        self.start.offset..self.end.offset
+    }
+}
+
+impl From<&Span> for Range<usize> {
+    fn from(span: &Span) -> Self {
+        span.as_range()
+    }
 }
 
 impl Default for Span {
@@ -455,12 +1599,49 @@ impl Serialize for Span {
     }
 }
 
+/// With the `simple_text` feature, text elements are serialized as plain strings,
+/// without position. Both representations are accepted when deserializing.
+#[cfg(feature = "simple_text")]
+impl Serialize for Element {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Element::Text(ref text) => serializer.serialize_str(&text.text),
+            _ => Element::serialize(self, serializer),
+        }
+    }
+}
+
+#[cfg(feature = "simple_text")]
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Simple(String),
+            #[serde(deserialize_with = "Element::deserialize")]
+            Tagged(Element),
+        }
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Simple(text) => Element::Text(Text {
+                position: Span::any(),
+                id: None,
+                text,
+            }),
+            Repr::Tagged(elem) => elem,
+        })
+    }
+}
+
 impl PartialEq for Position {
     fn eq(&self, other: &Position) -> bool {
         // comparing with "any" position is always true
-        if (other.offset == 0 && other.line == 0 && other.col == 0)
-            || (self.offset == 0 && self.line == 0 && self.col == 0)
-        {
+        if other.is_any() || self.is_any() {
             return true;
         }
 
@@ -468,12 +1649,529 @@ impl PartialEq for Position {
     }
 }
 
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
 impl TagAttribute {
     pub fn new(position: Span, key: String, value: String) -> Self {
         TagAttribute {
             position,
             key,
             value,
+            value_elements: vec![],
+        }
+    }
+
+    /// The value with html entities like `&amp;` or `&#65;` decoded (see `decode_entities`).
+    pub fn decoded_value(&self) -> String {
+        decode_entities(&self.value).into_owned()
+    }
+}
+
+/// Named html entities which are decoded by `decode_entities`.
+pub const NAMED_ENTITIES: [(&str, char); 28] = [
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("shy", '\u{ad}'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("hellip", '…'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("bull", '•'),
+    ("middot", '·'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("deg", '°'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("minus", '−'),
+    ("euro", '€'),
+    ("sect", '§'),
+    ("para", '¶'),
+];
+
+/// Decode html entities: the named entities of `NAMED_ENTITIES` and numeric
+/// character references like `&#65;` or `&#x41;`. Unknown or invalid entities are kept.
+pub fn decode_entities(text: &str) -> Cow<'_, str> {
+    // entity names are short, a `;` further away does not end an entity.
+    const MAX_ENTITY_LEN: usize = 32;
+
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let decoded = rest
+            .char_indices()
+            .take(MAX_ENTITY_LEN)
+            .find(|&(_, c)| c == ';')
+            .and_then(|(end, _)| Some((decode_entity(&rest[..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => result.push('&'),
         }
     }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+/// Decode the name of a single entity, without `&` and `;`.
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                u32::from_str_radix(hex, 16).ok()?
+            }
+            None if number.chars().all(|c| c.is_ascii_digit()) => number.parse().ok()?,
+            _ => return None,
+        };
+        return char::from_u32(code).filter(|&c| c != '\0');
+    }
+    NAMED_ENTITIES
+        .iter()
+        .find(|(entity, _)| *entity == name)
+        .map(|&(_, c)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn first_inline(root: &Element) -> &Element {
+        match *root {
+            Element::Document(ref doc) => match doc.content[0] {
+                Element::Paragraph(ref par) => &par.content[0],
+                ref e => e,
+            },
+            _ => panic!("not a document: {:?}", root),
+        }
+    }
+
+    #[test]
+    fn block_and_inline_elements() {
+        let heading = Element::Heading(Heading {
+            position: Span::any(),
+            id: None,
+            depth: 2,
+            open_markers: Some(2),
+            close_markers: Some(2),
+            caption: vec![],
+            content: vec![],
+        });
+        let text = Element::Text(Text {
+            position: Span::any(),
+            id: None,
+            text: "text".into(),
+        });
+        let formatted = |markup| {
+            Element::Formatted(Formatted {
+                position: Span::any(),
+                id: None,
+                markup,
+                content: vec![],
+            })
+        };
+        let row = Element::TableRow(TableRow {
+            position: Span::any(),
+            id: None,
+            attributes: vec![],
+            cells: vec![],
+        });
+
+        assert!(heading.is_block() && !heading.is_inline());
+        assert!(text.is_inline() && !text.is_block());
+        assert!(formatted(MarkupType::Bold).is_inline());
+        assert!(formatted(MarkupType::Blockquote).is_block());
+        assert!(!row.is_block() && !row.is_inline());
+    }
+
+    #[test]
+    fn internal_reference_namespace() {
+        let cases = [
+            ("[[Image:x.png|thumb]]", Some("Image"), Some("File")),
+            ("[[file:x.png]]", Some("file"), Some("File")),
+            ("[[:Category:Foo]]", Some("Category"), Some("Category")),
+            (
+                "[[User_talk:Someone]]",
+                Some("User_talk"),
+                Some("User talk"),
+            ),
+            ("[[Star Wars: Episode I]]", None, None),
+            ("[[Image]]", None, None),
+        ];
+        for &(input, raw, canonical) in &cases {
+            let root = parse(input).unwrap();
+            match *first_inline(&root) {
+                Element::InternalReference(ref iref) => {
+                    assert_eq!(iref.raw_namespace(), raw, "{}", input);
+                    assert_eq!(iref.namespace(), canonical, "{}", input);
+                }
+                ref e => panic!("not an internal reference: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn template_parser_function() {
+        let cases = [
+            ("{{#if: a | b}}", Some("#if")),
+            ("{{ #Switch:x|a=b}}", Some("#switch")),
+            ("{{UC:text}}", Some("uc")),
+            ("{{Template:Foo}}", None),
+            ("{{R from merge}}", None),
+            ("{{#unknown:x}}", None),
+            ("{{ns}}", None),
+        ];
+        for &(input, function) in &cases {
+            let root = parse(input).unwrap();
+            match *first_inline(&root) {
+                Element::Template(ref t) => assert_eq!(t.parser_function(), function, "{}", input),
+                ref e => panic!("not a template: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn template_name_matches() {
+        let template = |input: &str| match *first_inline(&parse(input).unwrap()) {
+            Element::Template(ref t) => t.clone(),
+            ref e => panic!("not a template: {:?}", e),
+        };
+        assert!(template("{{Foo}}").name_matches("foo"));
+        assert!(!template("{{FooBar}}").name_matches("foobar"));
+        assert!(template("{{ infobox_person |x}}").name_matches("Infobox  person"));
+        assert!(template("{{Template:Foo}}").name_matches("foo"));
+        assert!(template("{{foo}}").name_matches("template:Foo"));
+        assert!(!template("{{Foo}}").name_matches("Bar"));
+    }
+
+    #[test]
+    fn parser_functions_and_dynamic_names_never_match() {
+        let template = |input: &str| match *first_inline(&parse(input).unwrap()) {
+            Element::Template(ref t) => t.clone(),
+            ref e => panic!("not a template: {:?}", e),
+        };
+        assert!(!template("{{#if:x|a}}").name_matches("#switch"));
+        assert!(!template("{{#if:x|a}}").name_matches("#if"));
+        assert!(!template("{{#if:x|a}}").name_matches(""));
+        assert!(!template("{{ns:0}}").name_matches("ns:0"));
+        assert!(!template("{{ {{foo}}bar }}").name_matches("bar"));
+        assert!(!template("{{ {{foo}}bar }}").name_matches("foobar"));
+    }
+
+    #[test]
+    fn template_name_and_args() {
+        let template = match *first_inline(&parse("{{ Foo |bar| key = value }}").unwrap()) {
+            Element::Template(ref t) => t.clone(),
+            ref e => panic!("not a template: {:?}", e),
+        };
+        assert_eq!(template.name_str(), Some("Foo".to_string()));
+        assert!(template.get_arg("1").is_some());
+        assert!(template.get_arg("key").is_some());
+        assert!(template.get_arg("2").is_none());
+
+        let dynamic = match *first_inline(&parse("{{ {{foo}}bar }}").unwrap()) {
+            Element::Template(ref t) => t.clone(),
+            ref e => panic!("not a template: {:?}", e),
+        };
+        assert_eq!(dynamic.name_str(), None);
+
+        let multiline = match *first_inline(&parse("{{ Foo_bar \n | a \n | b = c\n}}").unwrap()) {
+            Element::Template(ref t) => t.clone(),
+            ref e => panic!("not a template: {:?}", e),
+        };
+        assert_eq!(multiline.name_str(), Some("Foo_bar".to_string()));
+        assert!(multiline.name_matches("foo bar"));
+        let value = &multiline.get_arg("b").unwrap().value;
+        assert_eq!(crate::extract::text_content_vec(value), "c");
+        assert_eq!(multiline.position.end.line, 4);
+    }
+
+    #[test]
+    fn variant_conversions() {
+        let root = parse("{{name|arg}}").unwrap();
+        let elem = first_inline(&root).clone();
+        assert_eq!(elem.as_template().map(|t| t.content.len()), Some(1));
+        assert!(elem.as_text().is_none());
+
+        let template = Template::try_from(elem.clone()).expect("conversion failed!");
+        assert_eq!(Element::Template(template), elem);
+
+        let err = Text::try_from(elem).unwrap_err();
+        assert_eq!(err.expected, "Text");
+        assert_eq!(err.found, "Template");
+    }
+
+    #[test]
+    fn list_item_kind_from_marker() {
+        let kinds: Vec<_> = LIST_MARKERS
+            .iter()
+            .map(|&m| ListItemKind::from_marker(m))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Some(ListItemKind::Unordered),
+                Some(ListItemKind::Ordered),
+                Some(ListItemKind::Definition),
+                Some(ListItemKind::DefinitionTerm),
+            ]
+        );
+        assert_eq!(ListItemKind::from_marker('-'), None);
+    }
+
+    #[test]
+    fn table_classes() {
+        let root =
+            parse("{| class=\"wikitable  sortable\" style=\"color: red\"\n| cell\n|}").unwrap();
+        let table = match root {
+            Element::Document(ref doc) => doc.content[0].as_table().unwrap().clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(table.classes(), vec!["wikitable", "sortable"]);
+        assert!(table.has_class("sortable"));
+        assert!(!table.has_class("sort"));
+        assert!(!table.has_class("color:"));
+
+        let plain = Table::default();
+        assert!(plain.classes().is_empty());
+        assert!(!plain.has_class("wikitable"));
+    }
+
+    #[test]
+    fn table_alignment() {
+        let root = parse(
+            "{| align=center style=\"vertical-align: bottom\"\n\
+             |- valign=top style=\"text-align: right\"\n\
+             | align=left style=\"text-align: Justify\" | a\n\
+             | style=\"text-align: nowhere\" align=right valign=middle | b\n|}",
+        )
+        .unwrap();
+        let table = root.as_document().unwrap().content[0].as_table().unwrap();
+        assert_eq!(table.horizontal_align(), Some(Align::Center));
+        assert_eq!(table.vertical_align(), Some(Align::Bottom));
+
+        let row = table.rows[0].as_table_row().unwrap();
+        assert_eq!(row.horizontal_align(), Some(Align::Right));
+        assert_eq!(row.vertical_align(), Some(Align::Top));
+
+        let cell = |i: usize| row.cells[i].as_table_cell().unwrap();
+        // style takes precedence over the legacy attribute.
+        assert_eq!(cell(0).horizontal_align(), Some(Align::Justify));
+        assert_eq!(cell(0).vertical_align(), None);
+        // invalid css values are ignored.
+        assert_eq!(cell(1).horizontal_align(), Some(Align::Right));
+        assert_eq!(cell(1).vertical_align(), Some(Align::Middle));
+    }
+
+    #[test]
+    fn table_and_cell_attributes() {
+        let root = parse(
+            "{| Border=1 style=\"width: 50%; COLOR:red;\"\n| style=\"color: blue\" | cell\n|}",
+        )
+        .unwrap();
+        let table = match root {
+            Element::Document(ref doc) => doc.content[0].as_table().unwrap().clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(table.attr("border"), Some("1"));
+        assert_eq!(table.attr("class"), None);
+        let style: Vec<_> = table.style_map().into_iter().collect();
+        assert_eq!(
+            style,
+            vec![
+                ("color".to_string(), "red".to_string()),
+                ("width".to_string(), "50%".to_string())
+            ]
+        );
+
+        let cell = table.rows[0].as_table_row().unwrap().cells[0]
+            .as_table_cell()
+            .unwrap();
+        assert_eq!(
+            cell.style_map().get("color").map(String::as_str),
+            Some("blue")
+        );
+        assert!(Table::default().style_map().is_empty());
+    }
+
+    #[test]
+    fn known_tag_names() {
+        assert!(known_extension_tags().contains(&"ref"));
+        assert!(known_extension_tags().contains(&"nowiki"));
+        assert!(known_tags().contains(&"span"));
+        assert!(!known_tags().contains(&"frobnicate"));
+
+        // tags the parser handles specially must be known.
+        let known =
+            |name: &str| known_tags().contains(&name) || known_extension_tags().contains(&name);
+        for (name, markup) in MARKUP_TAGS.iter() {
+            assert!(known(name), "{} is not a known tag!", name);
+            assert_eq!(MarkupType::by_tag_name(&name.to_uppercase()), Some(*markup));
+            let root = parse(&format!("<{0}>x</{0}>", name)).unwrap();
+            match *first_inline(&root) {
+                Element::Formatted(ref f) => assert_eq!(f.markup, *markup),
+                ref e => panic!("<{}> is not parsed as markup: {:?}", name, e),
+            }
+        }
+        assert!(known("gallery"));
+        assert_eq!(MarkupType::by_tag_name("span"), None);
+    }
+
+    #[test]
+    fn grammar_tags_match_tables() {
+        // the grammar has a rule for every tag in `MARKUP_TAGS`, and for no other tag.
+        for name in known_tags().iter().chain(known_extension_tags()) {
+            let root = parse(&format!("<{0}>x</{0}>", name)).unwrap();
+            let parsed = match *first_inline(&root) {
+                Element::Formatted(ref f) => Some(f.markup),
+                _ => None,
+            };
+            assert_eq!(parsed, MarkupType::by_tag_name(name), "<{}>", name);
+        }
+    }
+
+    #[test]
+    fn markup_type_result() {
+        assert_eq!(
+            MarkupType::try_by_tag_name("del"),
+            Ok(MarkupType::StrikeThrough)
+        );
+        assert_eq!(
+            MarkupType::try_by_tag_name("NoWiki"),
+            Ok(MarkupType::NoWiki)
+        );
+        assert_eq!(MarkupType::try_by_tag_name("span"), Err("span".to_string()));
+        assert_eq!(MarkupType::try_by_tag_name("b"), Err("b".to_string()));
+    }
+
+    #[test]
+    fn conditional_comment_text() {
+        assert!(Comment::is_conditional_text("[if IE]><p>old</p><![endif]"));
+        assert!(Comment::is_conditional_text(" [If lt IE 9]>x<![endif]"));
+        assert!(Comment::is_conditional_text("<![endif]"));
+        assert!(!Comment::is_conditional_text(" note [if IE]> "));
+        assert!(!Comment::is_conditional_text("[if you like]"));
+    }
+
+    #[test]
+    fn iterate_document() {
+        let root = parse("== A ==\ntext\n== B ==\n").unwrap();
+        let doc = root.as_document().unwrap();
+        let mut captions = vec![];
+        for elem in doc {
+            captions.push(crate::extract::text_content_vec(
+                &elem.as_heading().unwrap().caption,
+            ));
+        }
+        assert_eq!(captions, vec!["A", "B"]);
+        assert_eq!(doc.into_iter().count(), doc.content.len());
+    }
+
+    #[test]
+    fn default_elements() {
+        let heading = Heading {
+            depth: 2,
+            ..Default::default()
+        };
+        assert_eq!(heading.position, Span::any());
+        assert!(heading.caption.is_empty() && heading.content.is_empty());
+
+        let template = Template::default();
+        assert!(template.name.is_empty() && template.content.is_empty());
+
+        let table = Table::default();
+        assert!(table.rows.is_empty() && table.attributes.is_empty());
+        assert_eq!(ListItem::default().kind, ListItemKind::Unordered);
+    }
+
+    #[test]
+    fn enclosing_span() {
+        let root = parse("''a'' [[b]] {{c}}").unwrap();
+        let content = match root {
+            Element::Document(ref doc) => doc.content[0].as_paragraph().unwrap().content.clone(),
+            _ => unreachable!(),
+        };
+        let children: Vec<_> = content
+            .into_iter()
+            .filter(|e| e.get_variant_name() != "Text")
+            .collect();
+        assert_eq!(children.len(), 3);
+
+        let span = Span::enclosing(&children);
+        assert_eq!((span.start.offset, span.end.offset), (0, 17));
+        assert_eq!((span.start.col, span.end.col), (1, 18));
+
+        let reversed = children[2].get_position().merge(children[0].get_position());
+        assert_eq!((reversed.start.offset, reversed.end.offset), (0, 17));
+        let any = Span::any().merge(children[1].get_position());
+        assert_eq!((any.start.offset, any.end.offset), (6, 11));
+        assert!(Span::enclosing(&[]).start.is_any());
+    }
+
+    #[test]
+    fn attribute_entities() {
+        let root = parse("<span title=\"A &amp; B &#65;&#x42; &unknown; &amp\">x</span>").unwrap();
+        let attr = &first_inline(&root).as_html_tag().unwrap().attributes[0];
+        assert_eq!(attr.value, "A &amp; B &#65;&#x42; &unknown; &amp");
+        assert_eq!(attr.decoded_value(), "A & B AB &unknown; &amp");
+
+        assert!(matches!(decode_entities("no entities"), Cow::Borrowed(_)));
+        assert_eq!(
+            decode_entities("&lt;b&gt; &#0; &#x; &#+65; &&amp;"),
+            "<b> &#0; &#x; &#+65; &&"
+        );
+        assert_eq!(decode_entities("&nbsp;&mdash;&#8212;"), "\u{a0}——");
+    }
+
+    #[test]
+    fn span_ranges() {
+        let input = "text [[link|caption]] more";
+        let root = parse(input).unwrap();
+        let link = &root.as_document().unwrap().content[0]
+            .as_paragraph()
+            .unwrap()
+            .content[1];
+        let range: Range<usize> = link.get_position().into();
+        assert_eq!(range, 5..21);
+        assert_eq!(&input[link.get_position().as_range()], "[[link|caption]]");
+        assert_eq!(&input[root.get_position().as_range()], input);
+        assert_eq!(Span::any().as_range(), 0..0);
+        assert_eq!(&input[Range::from(&Span::any())], "");
+    }
+
+    #[test]
+    fn hash_ignores_positions() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(elem: &Element) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            elem.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let first = parse("''a'' {{b|c=d}}").unwrap();
+        let moved = parse("\n\n''a'' {{b|c=d}}").unwrap();
+        assert_ne!(first, moved);
+        assert_eq!(hash(&first), hash(&moved));
+        assert_ne!(hash(&first), hash(&parse("''a'' {{b|c=x}}").unwrap()));
+    }
 }
@@ -0,0 +1,207 @@
+//! Block-wise parsing, e.g. for editors which cache the parse result of every block
+//! and only re-parse blocks which changed.
+
+use crate::ast::*;
+use crate::transformations::*;
+use crate::traversion::Traversion;
+use crate::util;
+use std::io;
+
+/// Maximum number of blocks following a block which cannot be parsed on its own
+/// that are joined with it.
+pub const MAX_JOINED_BLOCKS: usize = 16;
+
+/// Parse the input block by block. Every block is parsed as a document of its own,
+/// with positions relative to the whole input.
+///
+/// Blocks are separated by blank lines, headings start a new block.
+/// If a block cannot be parsed on its own or contains errors, e.g. because a template
+/// contains a blank line, it is joined with the following blocks, up to the next heading
+/// and at most `MAX_JOINED_BLOCKS` blocks. If that does not help, the block is parsed on
+/// its own, resulting in a document with `Error` elements, or an `Error` element if it
+/// cannot be parsed at all. This way, an unclosed construct only causes the following
+/// blocks of its section to be parsed again.
+pub fn parse_lines(input: &str) -> Vec<(Span, Element)> {
+    let source_lines = util::get_source_lines(input);
+    let blocks = split_blocks(&source_lines);
+    let block_end = |line: &SourceLine| line.end.min(input.len());
+    let is_complete = |doc: &Element| !contains_error(doc);
+
+    let mut result = vec![];
+    let mut first = 0;
+    while first < blocks.len() {
+        let start = source_lines[blocks[first].0].start;
+        let end = block_end(&source_lines[blocks[first].1]);
+        let mut last = first;
+        let mut parsed = crate::parse(&input[start..end]);
+
+        if !parsed.as_ref().map(is_complete).unwrap_or(false) {
+            let last_joined = blocks.len().min(first + 1 + MAX_JOINED_BLOCKS);
+            for next in first + 1..last_joined {
+                if source_lines[blocks[next].0].content.starts_with('=') {
+                    break;
+                }
+                let end = block_end(&source_lines[blocks[next].1]);
+                match crate::parse(&input[start..end]) {
+                    Ok(ref doc) if is_complete(doc) => {
+                        parsed = Ok(doc.clone());
+                        last = next;
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let element = match parsed {
+            Ok(doc) => {
+                shift_positions(doc, (start, blocks[first].0)).expect("shifting positions failed!")
+            }
+            Err(e) => Element::Error(Error {
+                position: Span::new(start, end, &source_lines),
+                id: None,
+                message: format!("could not parse block: {}", e),
+            }),
+        };
+        result.push((element.get_position().clone(), element));
+        first = last + 1;
+    }
+    result
+}
+
+/// Split source lines into blocks, given as the indices of the first and last line.
+fn split_blocks(source_lines: &[SourceLine]) -> Vec<(usize, usize)> {
+    let mut blocks = vec![];
+    let mut current: Option<(usize, usize)> = None;
+    for (i, line) in source_lines.iter().enumerate() {
+        if util::is_whitespace(line.content) {
+            blocks.extend(current.take());
+            continue;
+        }
+        current = match current {
+            Some(block) if !line.content.starts_with('=') => Some((block.0, i)),
+            previous => {
+                blocks.extend(previous);
+                Some((i, i))
+            }
+        };
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Finds whether a tree contains an `Error` element. Once one is found,
+/// no further elements are visited.
+struct ErrorFinder<'e> {
+    path: Vec<&'e Element>,
+    found: bool,
+}
+
+impl<'e> Traversion<'e, ()> for ErrorFinder<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        if let Element::Error(_) = *root {
+            self.found = true;
+        }
+        Ok(!self.found)
+    }
+
+    fn work_vec(&mut self, _: &'e [Element], _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        Ok(!self.found)
+    }
+}
+
+/// Does the tree contain an `Error` element?
+fn contains_error(root: &Element) -> bool {
+    let mut finder = ErrorFinder {
+        path: vec![],
+        found: false,
+    };
+    finder
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    finder.found
+}
+
+/// Move all positions of a tree by a number of bytes and lines.
+/// "Any" positions are kept, as they do not refer to the source.
+fn shift_positions(mut root: Element, shift: (usize, usize)) -> TResult {
+    fn shift_span(span: &mut Span, (offset, lines): (usize, usize)) {
+        for position in [&mut span.start, &mut span.end] {
+            if position.is_any() {
+                continue;
+            }
+            position.offset += offset;
+            position.line += lines;
+        }
+    }
+
+    shift_span(root.get_position_mut(), shift);
+    for attr in attributes_mut(&mut root) {
+        shift_span(&mut attr.position, shift);
+    }
+    recurse_inplace(&shift_positions, root, shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_with_source_spans() {
+        let input = "first paragraph\nstill first\n\n== Heading ==\ntext\n\n{{template|\n\narg}}\n";
+        let blocks = parse_lines(input);
+        let lines: Vec<_> = blocks
+            .iter()
+            .map(|(span, _)| (span.start.line, span.end.line))
+            .collect();
+        assert_eq!(lines, vec![(1, 3), (4, 6), (7, 10)]);
+
+        for (span, block) in &blocks {
+            assert_eq!(block.get_variant_name(), "Document");
+            assert!(input[span.start.offset..span.end.offset].ends_with('\n'));
+        }
+        let heading = &blocks[1].1.as_document().unwrap().content[0];
+        let position = heading.as_heading().unwrap().caption[0].get_position();
+        assert_eq!((position.start.line, position.start.col), (4, 4));
+        assert_eq!(
+            &input[position.start.offset..position.end.offset],
+            "Heading "
+        );
+    }
+
+    #[test]
+    fn unclosed_table_keeps_following_blocks() {
+        let input = "{|\n| a\n\nb\n\n== H ==\ntext\n";
+        let blocks = parse_lines(input);
+        let lines: Vec<_> = blocks
+            .iter()
+            .map(|(span, block)| (span.start.line, block.get_variant_name()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(1, "Document"), (4, "Document"), (6, "Document")]
+        );
+    }
+
+    #[test]
+    fn shift_keeps_any_positions() {
+        let text = Element::Text(Text {
+            position: Span::any(),
+            id: None,
+            text: "generated".into(),
+        });
+        let shifted = shift_positions(text, (10, 2)).unwrap();
+        assert!(shifted.get_position().start.is_any());
+        assert!(shifted.get_position().end.is_any());
+    }
+}
@@ -1,11 +1,138 @@
 use crate::ast::*;
 use crate::error::TransformationError;
+use crate::extract::text_content_vec;
 use crate::transformations::*;
 use crate::util;
-use std::usize;
+use std::collections::HashSet;
 
-/// Settings for general transformations.
-pub struct GeneralSettings {}
+/// Settings for parsing and the general transformations.
+#[derive(Debug, Clone)]
+pub struct GeneralSettings {
+    /// Names of extension tags (lowercase) which are parsed as `ExtensionTag`,
+    /// keeping their body verbatim instead of parsing it as wiki text.
+    pub raw_extension_tags: HashSet<String>,
+    /// Names of behavior switches (uppercase, without underscores) which are
+    /// parsed as `MagicWord`, like `NOTOC` for `__NOTOC__`.
+    pub behavior_switches: HashSet<String>,
+    /// Describe the expected tokens of parse errors in words,
+    /// like "closing braces `}}`" instead of the raw grammar token `}}`.
+    pub friendly_errors: bool,
+    /// Depth of the headings created by `definition_terms_to_headings`.
+    pub definition_term_heading_depth: usize,
+    /// Merge text around comments in `collapse_consecutive_text`, as if the comments
+    /// were not there. The comments are kept after the merged text.
+    pub transparent_comments: bool,
+    /// Replace html tags which are neither in `HTML_TAGS`, nor in `EXTENSION_TAGS`
+    /// nor raw extension tags by `Error` elements, for strict validation.
+    /// Otherwise, unknown tags are kept as `HtmlTag`.
+    pub unknown_tags_as_errors: bool,
+    /// Prefixes (lowercase) of links to other wikis, like `wikipedia` in `[[wikipedia:Foo]]`.
+    pub interwiki_prefixes: HashSet<String>,
+}
+
+/// Common interwiki prefixes of the Wikimedia projects.
+pub const INTERWIKI_PREFIXES: [&str; 24] = [
+    "wikipedia",
+    "w",
+    "wiktionary",
+    "wikt",
+    "wikibooks",
+    "b",
+    "wikiquote",
+    "q",
+    "wikisource",
+    "s",
+    "wikinews",
+    "n",
+    "wikiversity",
+    "v",
+    "wikivoyage",
+    "voy",
+    "wikidata",
+    "d",
+    "commons",
+    "c",
+    "meta",
+    "m",
+    "mediawikiwiki",
+    "mw",
+];
+
+/// The behavior switches known to MediaWiki.
+pub const BEHAVIOR_SWITCHES: [&str; 22] = [
+    "NOTOC",
+    "FORCETOC",
+    "TOC",
+    "NOEDITSECTION",
+    "NEWSECTIONLINK",
+    "NONEWSECTIONLINK",
+    "NOGALLERY",
+    "HIDDENCAT",
+    "EXPECTUNUSEDCATEGORY",
+    "NOCONTENTCONVERT",
+    "NOCC",
+    "NOTITLECONVERT",
+    "NOTC",
+    "START",
+    "END",
+    "INDEX",
+    "NOINDEX",
+    "STATICREDIRECT",
+    "EXPECTUNUSEDTEMPLATE",
+    "NOGLOBAL",
+    "DISAMBIG",
+    "ARCHIVEDTALK",
+];
+
+impl GeneralSettings {
+    /// Is `name` the name of an extension tag with a verbatim body?
+    pub fn is_raw_extension_tag(&self, name: &str) -> bool {
+        self.raw_extension_tags.contains(&name.to_lowercase())
+    }
+
+    /// Extension tags which the parser recognizes with these settings,
+    /// `EXTENSION_TAGS` and the raw extension tags, sorted.
+    pub fn known_extension_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = EXTENSION_TAGS.iter().map(|t| t.to_string()).collect();
+        tags.extend(self.raw_extension_tags.iter().cloned());
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Is `name` the name of an extension tag the parser recognizes with these settings?
+    pub fn is_known_extension_tag(&self, name: &str) -> bool {
+        EXTENSION_TAGS.contains(&name.to_lowercase().as_str()) || self.is_raw_extension_tag(name)
+    }
+
+    /// Is `name` (without underscores) the name of a behavior switch?
+    pub fn is_behavior_switch(&self, name: &str) -> bool {
+        self.behavior_switches.contains(&name.to_uppercase())
+    }
+
+    /// Is `prefix` the prefix of links to another wiki?
+    pub fn is_interwiki_prefix(&self, prefix: &str) -> bool {
+        self.interwiki_prefixes
+            .contains(&prefix.trim().to_lowercase())
+    }
+}
+
+impl Default for GeneralSettings {
+    fn default() -> Self {
+        GeneralSettings {
+            raw_extension_tags: ["score", "timeline"]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            behavior_switches: BEHAVIOR_SWITCHES.iter().map(|s| s.to_string()).collect(),
+            friendly_errors: false,
+            definition_term_heading_depth: 3,
+            transparent_comments: false,
+            unknown_tags_as_errors: false,
+            interwiki_prefixes: INTERWIKI_PREFIXES.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
 
 /// Moves flat headings into a hierarchical structure based on their depth.
 pub fn fold_headings_transformation(mut root: Element, settings: &GeneralSettings) -> TResult {
@@ -53,7 +180,7 @@ pub fn fold_headings_transformation(mut root: Element, settings: &GeneralSetting
         // recurse transformation
         result = apply_func_drain(trans, &mut result, settings)?;
         Ok(result)
-    };
+    }
     root = recurse_inplace_template(
         &fold_headings_transformation,
         root,
@@ -113,6 +240,7 @@ pub fn fold_lists_transformation(mut root: Element, settings: &GeneralSettings)
                         if result.is_empty() {
                             result.push(Element::ListItem(ListItem {
                                 position: cur_item.position.clone(),
+                                id: None,
                                 depth: lowest_depth,
                                 kind: cur_item.kind,
                                 content: vec![],
@@ -121,6 +249,7 @@ pub fn fold_lists_transformation(mut root: Element, settings: &GeneralSettings)
                         if let Some(&mut Element::ListItem(ref mut last)) = result.last_mut() {
                             last.content.push(Element::List(List {
                                 position: cur_item.position.clone(),
+                                id: None,
                                 content: vec![],
                             }));
                         } else {
@@ -147,7 +276,7 @@ pub fn fold_lists_transformation(mut root: Element, settings: &GeneralSettings)
         }
         result = apply_func_drain(trans, &mut result, settings)?;
         Ok(result)
-    };
+    }
 
     if let Element::List { .. } = root {
         root = recurse_inplace_template(
@@ -213,6 +342,7 @@ pub fn collapse_paragraphs(
                         last.content.push(Element::Text(Text {
                             text: " ".into(),
                             position: last.position.clone(),
+                            id: None,
                         }));
                         last.content.append(&mut par.content);
                         last.position.end = par.position.end.clone();
@@ -237,6 +367,7 @@ pub fn collapse_paragraphs(
 }
 
 /// Collapse consecutive text tags into one, removing duplicate whitespace.
+/// Comments separate text, unless `transparent_comments` is set.
 pub fn collapse_consecutive_text(
     mut root: Element,
     settings: &GeneralSettings,
@@ -247,6 +378,8 @@ pub fn collapse_consecutive_text(
         settings: &'a GeneralSettings,
     ) -> TListResult {
         let mut result = vec![];
+        // comments skipped while merging text, moved after the merged text.
+        let mut comments = vec![];
 
         for mut child in root_content.drain(..) {
             if let Element::Text(ref mut text) = child {
@@ -259,9 +392,18 @@ pub fn collapse_consecutive_text(
                     last.position.end = text.position.end.clone();
                     continue;
                 }
+            } else if let Element::Comment(_) = child {
+                if settings.transparent_comments {
+                    if let Some(&Element::Text(_)) = result.last() {
+                        comments.push(child);
+                        continue;
+                    }
+                }
             };
+            result.append(&mut comments);
             result.push(child);
         }
+        result.append(&mut comments);
         result = apply_func_drain(trans, &mut result, settings)?;
         Ok(result)
     }
@@ -269,6 +411,125 @@ pub fn collapse_consecutive_text(
     Ok(root)
 }
 
+/// Replace unknown html tags by `Error` elements if `unknown_tags_as_errors` is set.
+/// The error covers the opening tag, the content of the tag is kept after it.
+pub fn unknown_tags_to_errors(root: Element, settings: &GeneralSettings) -> TResult {
+    fn splice(elem: Element, settings: &GeneralSettings, result: &mut Vec<Element>) {
+        let tag = match elem {
+            Element::HtmlTag(tag) => tag,
+            _ => return result.push(elem),
+        };
+        let name = tag.name.trim().to_lowercase();
+        if HTML_TAGS.contains(&name.as_str()) || settings.is_known_extension_tag(&name) {
+            return result.push(Element::HtmlTag(tag));
+        }
+        let mut position = tag.position.clone();
+        if let Some(first) = tag.content.first() {
+            position.end = first.get_position().start.clone();
+        }
+        result.push(Element::Error(Error {
+            position,
+            id: None,
+            message: format!("unknown html tag \"{}\".", tag.name),
+        }));
+        for child in tag.content {
+            splice(child, settings, result);
+        }
+    }
+
+    fn splice_unknown_tags<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut result = vec![];
+        for child in root_content.drain(..) {
+            splice(child, settings, &mut result);
+        }
+        apply_func_drain(trans, &mut result, settings)
+    }
+
+    if !settings.unknown_tags_as_errors {
+        return Ok(root);
+    }
+    recurse_inplace_template(
+        &unknown_tags_to_errors,
+        root,
+        settings,
+        &splice_unknown_tags,
+    )
+}
+
+/// Use the text after the pipe of a category assignment like `[[Category:Foo|Key]]`
+/// as its sort key, instead of a caption. Links to categories (`[[:Category:Foo|x]]`)
+/// keep their caption.
+pub fn category_sort_keys(mut root: Element, settings: &GeneralSettings) -> TResult {
+    if let Element::InternalReference(ref mut iref) = root {
+        let is_link = match iref.target.first() {
+            Some(Element::Text(ref t)) => t.text.trim_start().starts_with(':'),
+            _ => false,
+        };
+        let has_key = !iref.options.is_empty() || !iref.caption.is_empty();
+        if iref.namespace() == Some("Category") && !is_link && has_key {
+            let mut parts: Vec<String> = iref.options.iter().map(|o| text_content_vec(o)).collect();
+            parts.push(text_content_vec(&iref.caption));
+            iref.sort_key = Some(parts.join("|"));
+            iref.options.clear();
+            iref.caption.clear();
+        }
+    };
+    recurse_inplace(&category_sort_keys, root, settings)
+}
+
+/// Set the interwiki prefix of links to other wikis, like `wikipedia` for `[[wikipedia:Foo]]`,
+/// if the prefix is one of `interwiki_prefixes`.
+pub fn interwiki_links(mut root: Element, settings: &GeneralSettings) -> TResult {
+    if let Element::InternalReference(ref mut iref) = root {
+        if let Some(Element::Text(ref t)) = iref.target.first() {
+            let target = t.text.trim_start().trim_start_matches(':');
+            if let Some((prefix, _)) = target.split_once(':') {
+                if iref.namespace().is_none() && settings.is_interwiki_prefix(prefix) {
+                    iref.interwiki = Some(prefix.trim().to_lowercase());
+                }
+            }
+        }
+    };
+    recurse_inplace(&interwiki_links, root, settings)
+}
+
+/// Move the `link=` option of a file like `[[File:x.png|link=Page]]` to the `link`
+/// field of the reference. As the last part, the option is no caption.
+pub fn file_link_options(mut root: Element, settings: &GeneralSettings) -> TResult {
+    if let Element::InternalReference(ref mut iref) = root {
+        let is_link = match iref.target.first() {
+            Some(Element::Text(ref t)) => t.text.trim_start().starts_with(':'),
+            _ => false,
+        };
+        if iref.namespace() == Some("File") && !is_link {
+            let link_target = |part: &[Element]| {
+                let text = text_content_vec(part);
+                text.trim()
+                    .strip_prefix("link=")
+                    .map(|link| link.trim().to_string())
+            };
+            // like in MediaWiki, the last `link=` option wins.
+            let mut options = vec![];
+            for option in iref.options.drain(..) {
+                match link_target(&option) {
+                    Some(link) => iref.link = Some(link),
+                    None => options.push(option),
+                }
+            }
+            iref.options = options;
+            if let Some(link) = link_target(&iref.caption) {
+                iref.link = Some(link);
+                iref.caption.clear();
+            }
+        }
+    };
+    recurse_inplace(&file_link_options, root, settings)
+}
+
 /// Enumerate anonymous template arguments as "1", "2", ...
 pub fn enumerate_anon_args(mut root: Element, settings: &GeneralSettings) -> TResult {
     if let Element::Template(ref mut template) = root {
@@ -286,6 +547,74 @@ pub fn enumerate_anon_args(mut root: Element, settings: &GeneralSettings) -> TRe
     recurse_inplace(&enumerate_anon_args, root, settings)
 }
 
+/// Build the tags generated by `{{#tag:name|content|attribute=value}}`.
+/// Tags listed in `raw_extension_tags` become extension tags with a verbatim body.
+/// Needs enumerated template arguments.
+pub fn tag_functions_to_tags(mut root: Element, settings: &GeneralSettings) -> TResult {
+    let generated = match root {
+        Element::Template(ref mut template) => build_generated_tag(template, settings),
+        _ => None,
+    };
+    if let Some(tag) = generated {
+        root = tag;
+    }
+    recurse_inplace(&tag_functions_to_tags, root, settings)
+}
+
+/// Convert a `#tag` parser function call to the tag it generates.
+fn build_generated_tag(template: &mut Template, settings: &GeneralSettings) -> Option<Element> {
+    if template.parser_function() != Some("#tag") || template.name.len() != 1 {
+        return None;
+    }
+    let name = match template.name[0] {
+        Element::Text(ref t) => t.text.split_once(':')?.1.trim().to_lowercase(),
+        _ => return None,
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let mut content = vec![];
+    let mut attributes = vec![];
+    for child in template.content.drain(..) {
+        if let Element::TemplateArgument(mut arg) = child {
+            if arg.name == "1" {
+                content.append(&mut arg.value);
+            } else if arg.name.parse::<usize>().is_err() {
+                let value = text_content_vec(&arg.value);
+                let value = value.trim();
+                // like mediawiki, strip one pair of quotes around the value
+                let unquoted = ['"', '\'']
+                    .iter()
+                    .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+                    .unwrap_or(value);
+                attributes.push(TagAttribute::new(
+                    arg.position,
+                    arg.name.trim().to_string(),
+                    unquoted.to_string(),
+                ));
+            }
+        }
+    }
+    let position = template.position.clone();
+    Some(if settings.is_raw_extension_tag(&name) {
+        Element::ExtensionTag(ExtensionTag {
+            position,
+            id: None,
+            name,
+            attributes,
+            body: text_content_vec(&content),
+        })
+    } else {
+        Element::HtmlTag(HtmlTag {
+            position,
+            id: None,
+            name,
+            attributes,
+            content,
+        })
+    })
+}
+
 // taken from https://github.com/portstrom/parse_wiki_text/blob/master/src/default.rs
 const PROTOCOLS: [&str; 28] = [
     "//",
@@ -339,11 +668,11 @@ pub fn validate_external_refs(mut root: Element, settings: &GeneralSettings) ->
                             start: eref.position.start.clone(),
                             end: eref
                                 .caption
-                                .iter()
-                                .next()
+                                .first()
                                 .map(|c| c.get_position().start.clone())
-                                .unwrap_or(eref.position.end.clone()),
+                                .unwrap_or_else(|| eref.position.end.clone()),
                         },
+                        id: None,
                         text: format!("[{}", eref.target),
                     }));
                     result.append(&mut eref.caption);
@@ -357,6 +686,7 @@ pub fn validate_external_refs(mut root: Element, settings: &GeneralSettings) ->
                             },
                             end: eref.position.end.clone(),
                         },
+                        id: None,
                         text: "]".to_string(),
                     }));
                 }
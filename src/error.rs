@@ -1,8 +1,8 @@
 //! Error structures
 
-use crate::ast::{Element, Position, Span};
+use crate::ast::{Element, Position, SourceLine, Span};
 use crate::grammar;
-use crate::util::{get_source_lines, is_whitespace, shorten_str};
+use crate::util::{get_source_lines, is_whitespace, shorten_str, shorten_str_around};
 use colored::*;
 use serde_derive::{Deserialize, Serialize};
 use std::error;
@@ -11,12 +11,36 @@ use std::fmt;
 /// The number of lines to display as error context.
 const ERROR_CONTEXT_LINES: usize = 5;
 
+/// Descriptions of common grammar tokens for friendly error messages.
+const TOKEN_DESCRIPTIONS: [(&str, &str); 18] = [
+    ("}}", "closing braces `}}`"),
+    ("{{", "template start `{{`"),
+    ("]]", "closing brackets `]]`"),
+    ("[[", "internal reference start `[[`"),
+    ("]", "closing bracket `]`"),
+    ("[", "external reference start `[`"),
+    ("{|", "table start `{|`"),
+    ("|", "pipe `|`"),
+    ("=", "equals sign `=`"),
+    ("<!--", "comment start `<!--`"),
+    ("-->", "comment end `-->`"),
+    ("''", "italic markup `''`"),
+    ("'''", "bold markup `'''`"),
+    ("list marker", "list item marker"),
+    (" ", "whitespace"),
+    ("\t", "whitespace"),
+    ("[ \t]", "whitespace"),
+    ("\n", "line break"),
+];
+
 /// Generic error type for high-level errors of this libaray.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub enum MWError {
     ParseError(ParseError),
     TransformationError(TransformationError),
+    /// Reading the input failed, with the error message.
+    IoError(String),
 }
 
 /// The parser error with source code context.
@@ -28,6 +52,35 @@ pub struct ParseError {
     pub context: Vec<String>,
     pub context_start: usize,
     pub context_end: usize,
+    /// The complete error as reported by the grammar, not serialized.
+    #[serde(skip)]
+    pub raw: Option<RawParseError>,
+}
+
+/// The unprocessed error of the generated grammar, for advanced tooling.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawParseError {
+    /// Line of the error, starting at 1. May be past the last line for errors at the end of input.
+    pub line: usize,
+    /// Column of the error in characters, starting at 1.
+    pub column: usize,
+    /// Byte offset of the error in the input.
+    pub offset: usize,
+    /// All tokens the grammar expected, sorted and without descriptions.
+    pub expected: Vec<&'static str>,
+}
+
+impl<'a> From<&'a grammar::ParseError> for RawParseError {
+    fn from(err: &'a grammar::ParseError) -> Self {
+        let mut expected: Vec<_> = err.expected.iter().cloned().collect();
+        expected.sort_unstable();
+        RawParseError {
+            line: err.line,
+            column: err.column,
+            offset: err.offset,
+            expected,
+        }
+    }
 }
 
 /// Error structure for syntax tree transformations.
@@ -42,7 +95,16 @@ pub struct TransformationError {
 
 impl ParseError {
     pub fn from(err: &grammar::ParseError, input: &str) -> Self {
-        let source_lines = get_source_lines(input);
+        Self::with_source_lines(err, &get_source_lines(input), false)
+    }
+
+    /// Build a parse error from precomputed source lines of the input.
+    /// If `friendly` is set, common expected tokens are described in words.
+    pub(crate) fn with_source_lines(
+        err: &grammar::ParseError,
+        source_lines: &[SourceLine],
+        friendly: bool,
+    ) -> Self {
         let line_count = source_lines.len();
 
         let line = if err.line <= line_count {
@@ -51,11 +113,7 @@ impl ParseError {
             source_lines.len()
         } - 1;
 
-        let start = if line < ERROR_CONTEXT_LINES {
-            0
-        } else {
-            line - ERROR_CONTEXT_LINES
-        };
+        let start = line.saturating_sub(ERROR_CONTEXT_LINES);
 
         let end = if line + ERROR_CONTEXT_LINES >= line_count {
             line_count - 1
@@ -63,9 +121,16 @@ impl ParseError {
             line + ERROR_CONTEXT_LINES
         };
 
-        let mut token_str = vec![];
+        let mut token_str: Vec<String> = vec![];
         for token in &err.expected {
-            token_str.push(String::from(*token));
+            let token = if friendly {
+                describe_token(token)
+            } else {
+                token
+            };
+            if !token_str.iter().any(|t| t == token) {
+                token_str.push(String::from(token));
+            }
         }
 
         let mut context = vec![];
@@ -74,15 +139,25 @@ impl ParseError {
         }
 
         ParseError {
-            position: Position::new(err.offset, &source_lines),
+            position: Position::new(err.offset, source_lines),
             context,
             expected: token_str,
             context_start: start,
             context_end: end,
+            raw: Some(RawParseError::from(err)),
         }
     }
 }
 
+/// Get the description of a grammar token, or the token itself if it is unknown.
+fn describe_token(token: &str) -> &str {
+    TOKEN_DESCRIPTIONS
+        .iter()
+        .find(|(raw, _)| *raw == token)
+        .map(|(_, description)| *description)
+        .unwrap_or(token)
+}
+
 impl error::Error for ParseError {
     fn description(&self) -> &str {
         "Could not continue to parse, because no rules could be matched."
@@ -117,10 +192,10 @@ impl fmt::Display for ParseError {
             let formatted_content;
             // the erroneous line
             if self.context_start + i + 1 == self.position.line {
-                formatted_content = content.red();
+                formatted_content = shorten_str_around(content, self.position.col).red();
                 lineno_col = lineno.red().bold();
             } else {
-                formatted_content = shorten_str(content).normal();
+                formatted_content = shorten_str(content).as_ref().normal();
                 lineno_col = lineno.blue().bold()
             }
 
@@ -152,11 +227,13 @@ impl fmt::Display for TransformationError {
     }
 }
 
+#[allow(deprecated)]
 impl error::Error for MWError {
     fn description(&self) -> &str {
         match *self {
             MWError::ParseError(ref e) => e.description(),
             MWError::TransformationError(ref e) => e.description(),
+            MWError::IoError(ref message) => message,
         }
     }
 }
@@ -166,6 +243,7 @@ impl fmt::Display for MWError {
         match *self {
             MWError::ParseError(ref e) => write!(f, "{}", e),
             MWError::TransformationError(ref e) => write!(f, "{}", e),
+            MWError::IoError(ref message) => write!(f, "could not read input: {}", message),
         }
     }
 }
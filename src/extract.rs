@@ -0,0 +1,573 @@
+//! Functions extracting information from the document tree.
+
+use crate::ast::*;
+use crate::traversion::Traversion;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+
+/// Writes the text of a (sub-)tree to the output.
+///
+/// Templates are not expanded, so their content is ignored.
+/// The content of block elements is separated by newlines.
+struct TextCollector<'e> {
+    path: Vec<&'e Element>,
+}
+
+impl<'e> Traversion<'e, ()> for TextCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), out: &mut dyn io::Write) -> io::Result<bool> {
+        match *root {
+            Element::Text(ref e) => write!(out, "{}", e.text)?,
+            Element::Template(_) | Element::Parameter(_) => return Ok(false),
+            ref e if !e.is_inline() => writeln!(out)?,
+            _ => (),
+        };
+        Ok(true)
+    }
+}
+
+/// Get the text of a list of elements, e.g. a heading caption.
+pub fn text_content_vec(content: &[Element]) -> String {
+    text_content_iter(content)
+}
+
+/// Get the text of elements given by reference, e.g. a selection of
+/// the content of a heading, like `text_content_vec`.
+pub(crate) fn text_content_iter<'e>(content: impl IntoIterator<Item = &'e Element>) -> String {
+    let mut collector = TextCollector { path: vec![] };
+    let mut out = vec![];
+    for elem in content {
+        collector
+            .run(elem, (), &mut out)
+            .expect("writing to a buffer failed!");
+    }
+    String::from_utf8(out)
+        .expect("text is not valid utf-8!")
+        .trim()
+        .to_string()
+}
+
+/// Collects the internal references to a page.
+struct LinkCollector<'e> {
+    path: Vec<&'e Element>,
+    title: String,
+    links: Vec<&'e InternalReference>,
+}
+
+impl<'e> Traversion<'e, ()> for LinkCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        if let Element::InternalReference(ref iref) = *root {
+            if normalize_title(&text_content_vec(&iref.target)) == self.title {
+                self.links.push(iref);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Find all internal references to the page `title`, in document order.
+///
+/// Titles are compared after `normalize_title`, so `[[Some_page]]` and `[[:some page]]`
+/// link to `Some page`, and `[[Image:x.png]]` links to `File:x.png`.
+pub fn links_to<'e>(root: &'e Element, title: &str) -> Vec<&'e InternalReference> {
+    let mut collector = LinkCollector {
+        path: vec![],
+        title: normalize_title(title),
+        links: vec![],
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    collector.links
+}
+
+/// Collects external references.
+struct ExternalLinkCollector<'e> {
+    path: Vec<&'e Element>,
+    links: Vec<&'e ExternalReference>,
+}
+
+impl<'e> Traversion<'e, ()> for ExternalLinkCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        if let Element::ExternalReference(ref eref) = *root {
+            self.links.push(eref);
+        }
+        Ok(true)
+    }
+}
+
+/// Find all external references, e.g. `[https://example.com example]`, in document order.
+pub fn external_links(root: &Element) -> Vec<&ExternalReference> {
+    let mut collector = ExternalLinkCollector {
+        path: vec![],
+        links: vec![],
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    collector.links
+}
+
+/// The urls of all external references in document order, e.g. for link checking.
+/// Urls linked several times are listed every time.
+pub fn external_link_targets(root: &Element) -> Vec<&str> {
+    external_links(root)
+        .into_iter()
+        .map(|eref| eref.target.as_str())
+        .collect()
+}
+
+/// A `<ref>` tag with its number within its group.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NumberedReference<'e> {
+    /// The `group` attribute, empty for the default group.
+    pub group: String,
+    /// The number of the reference in its group, starting with 1.
+    /// References with the same name share a number.
+    pub number: usize,
+    pub tag: &'e HtmlTag,
+    /// The tag with the content of the reference. This is `tag` itself, or for
+    /// a reuse like `<ref name="a"/>` the reference with the same name which has
+    /// content, which may also be defined in the `<references>` list.
+    pub definition: Option<&'e HtmlTag>,
+}
+
+/// Numbers `<ref>` tags independently for every group.
+struct ReferenceCollector<'e> {
+    path: Vec<&'e Element>,
+    counters: HashMap<String, usize>,
+    names: HashMap<(String, String), usize>,
+    /// named references with content, by group and name.
+    definitions: HashMap<(String, String), &'e HtmlTag>,
+    references: Vec<NumberedReference<'e>>,
+}
+
+impl<'e> ReferenceCollector<'e> {
+    /// Remember a named reference with content as definition of its name.
+    fn define(&mut self, tag: &'e HtmlTag, default_group: &str) {
+        if let Some(name) = attribute(tag, "name") {
+            if !tag.content.is_empty() {
+                let group = attribute(tag, "group").unwrap_or(default_group);
+                self.definitions
+                    .entry((group.to_string(), name.to_string()))
+                    .or_insert(tag);
+            }
+        }
+    }
+
+    /// Collect the references defined in a `<references>` list.
+    fn define_listed(&mut self, content: &'e [Element], group: &str) {
+        for elem in content {
+            match *elem {
+                Element::HtmlTag(ref tag) if tag.name.eq_ignore_ascii_case("ref") => {
+                    self.define(tag, group)
+                }
+                Element::HtmlTag(ref tag) => self.define_listed(&tag.content, group),
+                Element::Paragraph(ref par) => self.define_listed(&par.content, group),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Get the value of an attribute of a tag.
+fn attribute<'t>(tag: &'t HtmlTag, key: &str) -> Option<&'t str> {
+    tag.attributes
+        .iter()
+        .find(|attr| attr.key.trim().eq_ignore_ascii_case(key))
+        .map(|attr| attr.value.trim())
+}
+
+impl<'e> Traversion<'e, ()> for ReferenceCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        let tag = match *root {
+            Element::HtmlTag(ref tag) => tag,
+            _ => return Ok(true),
+        };
+        match tag.name.to_lowercase().as_str() {
+            "ref" => (),
+            // references defined in the list are numbered where they are used.
+            "references" => {
+                let group = attribute(tag, "group").unwrap_or_default();
+                self.define_listed(&tag.content, group);
+                return Ok(false);
+            }
+            _ => return Ok(true),
+        };
+        self.define(tag, "");
+        let group = attribute(tag, "group").unwrap_or_default().to_string();
+        let named = attribute(tag, "name").map(|name| (group.clone(), name.to_string()));
+        let counters = &mut self.counters;
+        let mut next_number = || {
+            let counter = counters.entry(group.clone()).or_insert(0);
+            *counter += 1;
+            *counter
+        };
+        let number = match named {
+            Some(key) => *self.names.entry(key).or_insert_with(next_number),
+            None => next_number(),
+        };
+        self.references.push(NumberedReference {
+            group,
+            number,
+            tag,
+            definition: None,
+        });
+        Ok(true)
+    }
+}
+
+/// Number the `<ref>` tags of a document in document order.
+/// Every group (`<ref group="note">`) is numbered independently.
+///
+/// References defined in a `<references>` list are not numbered themselves,
+/// but are the definition of the references using their name.
+pub fn number_references(root: &Element) -> Vec<NumberedReference<'_>> {
+    let mut collector = ReferenceCollector {
+        path: vec![],
+        counters: HashMap::new(),
+        names: HashMap::new(),
+        definitions: HashMap::new(),
+        references: vec![],
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+
+    let definitions = collector.definitions;
+    let mut references = collector.references;
+    for reference in &mut references {
+        reference.definition = if reference.tag.content.is_empty() {
+            attribute(reference.tag, "name").and_then(|name| {
+                let key = (reference.group.clone(), name.to_string());
+                definitions.get(&key).cloned()
+            })
+        } else {
+            Some(reference.tag)
+        };
+    }
+    references
+}
+
+/// Select the references a `<references group="..."/>` tag lists, which are the
+/// references of its group. References sharing a number are listed once.
+pub fn references_for<'a, 'e>(
+    references: &'a [NumberedReference<'e>],
+    list: &HtmlTag,
+) -> Vec<&'a NumberedReference<'e>> {
+    let group = attribute(list, "group").unwrap_or_default();
+    let mut result: Vec<&NumberedReference> = vec![];
+    for reference in references.iter().filter(|r| r.group == group) {
+        if !result.iter().any(|r| r.number == reference.number) {
+            result.push(reference);
+        }
+    }
+    result
+}
+
+/// Get the text of an element and its children.
+pub fn text_content(root: &Element) -> String {
+    text_content_vec(std::slice::from_ref(root))
+}
+
+/// A template invocation with the text of its arguments.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct TemplateInfo {
+    /// The name of the template, `None` for parser functions like `{{#if: x | y}}`.
+    pub name: Option<String>,
+    /// Names and text content of the arguments, in order of appearance.
+    pub args: Vec<(String, String)>,
+    /// Position of the template.
+    pub position: Span,
+}
+
+/// Collects templates with static names.
+struct TemplateCollector<'e> {
+    path: Vec<&'e Element>,
+    templates: Vec<TemplateInfo>,
+}
+
+impl<'e> Traversion<'e, ()> for TemplateCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        if let Element::Template(ref template) = *root {
+            if let Some(name) = template.name_str() {
+                let args = template
+                    .content
+                    .iter()
+                    .filter_map(|e| e.as_template_argument())
+                    .map(|arg| (arg.name.trim().to_string(), text_content_vec(&arg.value)))
+                    .collect();
+                self.templates.push(TemplateInfo {
+                    name: template.parser_function().map_or(Some(name), |_| None),
+                    args,
+                    position: template.position.clone(),
+                });
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Get all template invocations in document order, including templates in arguments
+/// of other templates. Templates with dynamic names like `{{ {{foo}}bar }}` are skipped.
+pub fn template_invocations(root: &Element) -> Vec<TemplateInfo> {
+    let mut collector = TemplateCollector {
+        path: vec![],
+        templates: vec![],
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    collector.templates
+}
+
+/// Find template invocations lacking required arguments, e.g. for linting.
+///
+/// `required` maps template names to the names of their required arguments,
+/// positional arguments are named by their number. Template names are compared
+/// by title, so `{{foo_bar}}` matches `Template:Foo bar`. Returns the position of the
+/// template and the name of the missing argument for every argument missing.
+pub fn missing_args(
+    root: &Element,
+    required: &HashMap<String, Vec<String>>,
+) -> Vec<(Span, String)> {
+    let required: HashMap<String, &Vec<String>> = required
+        .iter()
+        .map(|(name, args)| (normalize_template_title(name), args))
+        .collect();
+    let mut missing = vec![];
+    for template in template_invocations(root) {
+        let args = match template
+            .name
+            .and_then(|name| required.get(&normalize_template_title(&name)))
+        {
+            Some(args) => args,
+            None => continue,
+        };
+        for arg in args.iter() {
+            if !template.args.iter().any(|(name, _)| name == arg.trim()) {
+                missing.push((template.position.clone(), arg.clone()));
+            }
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn text_of_formatted_paragraphs() {
+        let root =
+            parse("some ''formatted'' [https://example.com link] {{tpl|arg}}\n\nnext").unwrap();
+        assert_eq!(text_content(&root), "some formatted link \nnext");
+    }
+
+    #[test]
+    fn links_to_page() {
+        let root = parse(
+            "[[Main Page]], [[main_Page#top|home]], [[Other]]\n\
+             [[Help:Main Page]] [[:help:Main_Page]] [[Image:x.png]]",
+        )
+        .unwrap();
+        let targets = |title| -> Vec<_> {
+            links_to(&root, title)
+                .iter()
+                .map(|l| text_content_vec(&l.target))
+                .collect()
+        };
+        assert_eq!(targets("Main Page"), vec!["Main Page", "main_Page#top"]);
+        assert_eq!(targets("main_Page"), targets("Main Page"));
+        // only the first letter is case-insensitive.
+        assert!(targets("Main page").is_empty());
+        assert_eq!(
+            targets("Help:Main_Page"),
+            vec!["Help:Main Page", ":help:Main_Page"]
+        );
+        assert_eq!(targets("File:X.png"), vec!["Image:x.png"]);
+        assert!(targets("Missing").is_empty());
+    }
+
+    #[test]
+    fn external_link_targets_in_order() {
+        let root = parse(
+            "== [https://a.org heading] ==\n\
+             * [http://b.org] and [[internal]]\n\
+             {|\n| [//c.org ''cell''] {{t|[https://d.org arg]}}\n|}\n\
+             text<ref>[https://a.org again]</ref>",
+        )
+        .unwrap();
+        assert_eq!(external_links(&root).len(), 5);
+        assert_eq!(
+            external_link_targets(&root),
+            vec![
+                "https://a.org",
+                "http://b.org",
+                "//c.org",
+                "https://d.org",
+                "https://a.org"
+            ]
+        );
+        assert!(external_links(&parse("[[only internal]]").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn templates_with_args() {
+        let root = parse("{{Infobox|name=''Foo''|42}}\n\n{{ cite |title = {{bar}} x}}").unwrap();
+        let templates = template_invocations(&root);
+        let info = |name: &str, args: &[(&str, &str)]| TemplateInfo {
+            name: Some(name.to_string()),
+            args: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            position: Span::any(),
+        };
+        assert_eq!(
+            templates,
+            vec![
+                info("Infobox", &[("name", "Foo"), ("1", "42")]),
+                info("cite", &[("title", "x")]),
+                info("bar", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_missing_required_args() {
+        let input = "{{Infobox|name=Foo}} {{template:infobox|1|title=Bar}} {{other}}";
+        let root = parse(input).unwrap();
+        let required: HashMap<_, _> = vec![(
+            "Infobox".to_string(),
+            vec!["name".to_string(), "1".to_string()],
+        )]
+        .into_iter()
+        .collect();
+        let missing: Vec<_> = missing_args(&root, &required)
+            .into_iter()
+            .map(|(span, arg)| (&input[span.start.offset..span.end.offset], arg))
+            .collect();
+        assert_eq!(
+            missing,
+            vec![
+                ("{{Infobox|name=Foo}}", "1".to_string()),
+                ("{{template:infobox|1|title=Bar}}", "name".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_reference_numbers() {
+        let root = parse(
+            "a<ref>one</ref> b<ref group=\"note\">n1</ref> c<ref name=\"x\">two</ref> \
+             d<ref name=\"x\"/> e<ref group=note>n2</ref>\n<references group=\"note\"/>",
+        )
+        .unwrap();
+        let references = number_references(&root);
+        let numbers: Vec<_> = references
+            .iter()
+            .map(|r| (r.group.as_str(), r.number))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![("", 1), ("note", 1), ("", 2), ("", 2), ("note", 2)]
+        );
+
+        let list = match root {
+            Element::Document(ref doc) => match doc.content[0] {
+                Element::Paragraph(ref par) => par.content.last().unwrap().as_html_tag().unwrap(),
+                _ => panic!("expected a paragraph!"),
+            },
+            _ => unreachable!(),
+        };
+        let notes: Vec<_> = references_for(&references, list)
+            .iter()
+            .map(|r| text_content_vec(&r.tag.content))
+            .collect();
+        assert_eq!(notes, vec!["n1", "n2"]);
+
+        let default = HtmlTag {
+            name: "references".into(),
+            ..Default::default()
+        };
+        let texts: Vec<_> = references_for(&references, &default)
+            .iter()
+            .map(|r| text_content_vec(&r.tag.content))
+            .collect();
+        assert_eq!(texts, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn list_defined_references() {
+        let root = parse(
+            "a<ref name=\"x\"/> b<ref>inline</ref> c<ref name=\"x\"/> d<ref name=\"missing\"/>\n\
+             <references>\n<ref name=\"x\">listed</ref>\n</references>",
+        )
+        .unwrap();
+        let references: Vec<_> = number_references(&root)
+            .iter()
+            .map(|r| (r.number, r.definition.map(|d| text_content_vec(&d.content))))
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                (1, Some("listed".to_string())),
+                (2, Some("inline".to_string())),
+                (1, Some("listed".to_string())),
+                (3, None),
+            ]
+        );
+    }
+}
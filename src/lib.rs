@@ -1,13 +1,24 @@
+// MWError carries the tree a transformation failed on, so results are large.
+#![allow(clippy::result_large_err)]
+
 mod ast;
 mod error;
 #[allow(
     clippy::unused_unit,
     clippy::unit_arg,
-    clippy::cyclomatic_complexity,
+    clippy::cognitive_complexity,
     clippy::len_zero,
     clippy::single_match,
     clippy::naive_bytecount,
-    clippy::suspicious_else_formatting
+    clippy::suspicious_else_formatting,
+    clippy::possible_missing_else,
+    clippy::blocks_in_conditions,
+    clippy::collapsible_match,
+    clippy::needless_lifetimes,
+    clippy::implicit_saturating_sub,
+    clippy::type_complexity,
+    redundant_semicolons,
+    ellipsis_inclusive_range_patterns
 )]
 mod grammar;
 #[cfg(test)]
@@ -20,57 +31,307 @@ pub use self::ast::*;
 pub use self::error::*;
 pub use self::traversion::Traversion;
 
+pub mod blocks;
+pub mod extract;
+pub mod optional_transformations;
+pub mod pandoc;
+pub mod stats;
+pub mod toc;
+pub mod tokens;
 pub mod transformations;
+pub mod validate;
 
 mod default_transformations;
+pub use self::default_transformations::GeneralSettings;
 use self::default_transformations::*;
+use std::io;
 
 /// Parse the input document to generate a document tree.
 /// After parsing, some transformations are applied to the result.
 pub fn parse(input: &str) -> Result<Element, MWError> {
+    Parser::new(input).parse()
+}
+
+/// Parse the input as inline content, like formatted text, references and templates.
+/// Block elements like paragraphs, headings or lists are not recognized,
+/// which is useful for short snippets like captions or edit summaries.
+pub fn parse_inline(input: &str) -> Result<Vec<Element>, MWError> {
+    let source_lines = util::get_source_lines(input);
+    let settings = GeneralSettings::default();
+    let braces = util::ClosingBraces::default();
+    let content =
+        grammar::inline_content(input, &source_lines, &settings, &braces).map_err(|e| {
+            error::MWError::ParseError(error::ParseError::with_source_lines(
+                &e,
+                &source_lines,
+                false,
+            ))
+        })?;
+
+    // transformations are applied to the children of an element.
+    let root = Element::Paragraph(Paragraph {
+        position: Span::new(0, input.len(), &source_lines),
+        id: None,
+        content,
+    });
+    match apply_inline_transformations(root, &settings) {
+        Ok(Element::Paragraph(par)) => Ok(par.content),
+        Ok(_) => unreachable!("inline transformations changed the root element!"),
+        Err(e) => Err(error::MWError::TransformationError(e)),
+    }
+}
+
+/// Parse the input like `parse`, but always return a document tree.
+///
+/// If the input cannot be parsed as a whole, it is parsed block by block (see
+/// `blocks::parse_lines`). Blocks which cannot be parsed become `Error` elements
+/// spanning their source, the other blocks are parsed as usual and are structured
+/// like in the result of `parse`. As blocks are only joined within a section,
+/// a broken block costs at most a few parses of its section.
+pub fn parse_lossy(input: &str) -> Element {
+    if let Ok(root) = parse(input) {
+        return root;
+    }
     let source_lines = util::get_source_lines(input);
+    let mut content = vec![];
+    for (_, block) in blocks::parse_lines(input) {
+        match block {
+            Element::Document(doc) => content.extend(doc.content),
+            error => content.push(error),
+        }
+    }
+    let root = Element::Document(Document {
+        position: Span::new(0, input.len(), &source_lines),
+        id: None,
+        content,
+    });
+    // blocks following a heading belong to its section.
+    optional_transformations::fold_sections(root, &GeneralSettings::default())
+        .expect("folding sections failed!")
+}
 
-    #[cfg(feature = "ptime")]
-    let starttime = time::precise_time_ns();
-
-    let result = match grammar::document(input, &source_lines) {
-        Err(e) => Err(error::MWError::ParseError(error::ParseError::from(
-            &e, input,
-        ))),
-        Ok(r) => Ok(r),
-    }?;
-
-    #[cfg(feature = "ptime")]
-    let parsedtime = time::precise_time_ns();
-
-    let settings = GeneralSettings {};
-    let trans_result = apply_transformations(result, &settings);
-
-    #[cfg(feature = "ptime")]
-    {
-        eprintln!(
-            "Parse Timer: Parsing took {} ms.",
-            ((parsedtime - starttime) as f64) / 1.0e6
-        );
-        eprintln!(
-            "Parse Timer: Transformation took {} ms.",
-            ((time::precise_time_ns() - parsedtime) as f64) / 1.0e6
-        );
+/// Get the outline of a document as depth, caption text and position of every heading,
+/// in document order. This is faster than `parse`, as only the grammar is applied
+/// and just the headings are visited.
+pub fn outline(input: &str) -> Result<Vec<(usize, String, Span)>, MWError> {
+    fn collect_headings(content: &[Element], result: &mut Vec<(usize, String, Span)>) {
+        for elem in content {
+            if let Element::Heading(ref heading) = *elem {
+                result.push((
+                    heading.depth,
+                    extract::text_content_vec(&heading.caption),
+                    heading.position.clone(),
+                ));
+                collect_headings(&heading.content, result);
+            }
+        }
     }
 
-    trans_result.map_err(error::MWError::TransformationError)
+    let source_lines = util::get_source_lines(input);
+    let settings = GeneralSettings::default();
+    let braces = util::ClosingBraces::default();
+    let root = grammar::document(input, &source_lines, &settings, &braces).map_err(|e| {
+        error::MWError::ParseError(error::ParseError::with_source_lines(
+            &e,
+            &source_lines,
+            false,
+        ))
+    })?;
+    let mut result = vec![];
+    if let Element::Document(ref doc) = root {
+        collect_headings(&doc.content, &mut result);
+    }
+    Ok(result)
 }
 
+/// Parse a stream segment by segment, e.g. the articles of a large dump,
+/// without reading all of it into memory.
+///
+/// Segments are separated by lines consisting only of `delimiter`, empty segments are skipped.
+/// A read error is returned as `MWError::IoError` and ends the iteration.
+pub fn parse_stream<R: io::BufRead>(
+    reader: R,
+    delimiter: &str,
+) -> impl Iterator<Item = Result<Element, MWError>> {
+    let delimiter = delimiter.to_string();
+    let mut lines = reader.lines();
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        let mut segment = String::new();
+        while !failed {
+            match lines.next() {
+                Some(Ok(ref line)) if line.trim_end() == delimiter => {
+                    if !util::is_whitespace(&segment) {
+                        break;
+                    }
+                    segment.clear();
+                }
+                Some(Ok(line)) => {
+                    segment.push_str(&line);
+                    segment.push('\n');
+                }
+                Some(Err(e)) => {
+                    failed = true;
+                    return Some(Err(error::MWError::IoError(e.to_string())));
+                }
+                None => break,
+            }
+        }
+        if util::is_whitespace(&segment) {
+            None
+        } else {
+            Some(parse(&segment))
+        }
+    })
+}
+
+/// Parse a list of html tag attributes, like `class="x" style='y' border=1`.
+pub fn parse_attributes(input: &str) -> Result<Vec<TagAttribute>, MWError> {
+    let source_lines = util::get_source_lines(input);
+    let settings = GeneralSettings::default();
+    let braces = util::ClosingBraces::default();
+    grammar::attribute_list(input, &source_lines, &settings, &braces).map_err(|e| {
+        error::MWError::ParseError(error::ParseError::with_source_lines(
+            &e,
+            &source_lines,
+            false,
+        ))
+    })
+}
+
+/// Serialize a (sub-)tree to YAML, as done by `mwtoast`.
+pub fn to_yaml(elem: &Element) -> String {
+    serde_yaml::to_string(elem).expect("could not serialize yaml!")
+}
+
+/// Serialize a (sub-)tree to JSON, as done by `mwtoast --json`.
+pub fn to_json(elem: &Element) -> String {
+    serde_json::to_string(elem).expect("could not serialize json!")
+}
+
+/// Serialize a (sub-)tree to YAML, replacing everything nested deeper than `max_depth`
+/// by a placeholder (see `transformations::truncate_depth`).
+pub fn to_yaml_truncated(elem: &Element, max_depth: usize) -> String {
+    to_yaml(&transformations::truncate_depth(elem.clone(), max_depth))
+}
+
+/// Serialize a (sub-)tree to JSON, replacing everything nested deeper than `max_depth`
+/// by a placeholder (see `transformations::truncate_depth`).
+pub fn to_json_truncated(elem: &Element, max_depth: usize) -> String {
+    to_json(&transformations::truncate_depth(elem.clone(), max_depth))
+}
+
+/// Serialize a (sub-)tree to YAML, writing it to `out` without building a string first.
+pub fn to_yaml_writer<W: io::Write>(elem: &Element, out: W) -> io::Result<()> {
+    serde_yaml::to_writer(out, elem).map_err(io::Error::other)
+}
+
+/// Serialize a (sub-)tree to JSON, writing it to `out` without building a string first.
+pub fn to_json_writer<W: io::Write>(elem: &Element, out: W) -> io::Result<()> {
+    serde_json::to_writer(out, elem).map_err(io::Error::from)
+}
+
+/// A parser for a single input document.
+///
+/// The source line positions of the input are computed once on creation,
+/// so the same document can be parsed repeatedly (e.g. with different settings)
+/// without recomputing them. The same holds for the matching braces of templates.
+pub struct Parser<'input> {
+    input: &'input str,
+    source_lines: Vec<SourceLine<'input>>,
+    braces: util::ClosingBraces,
+}
+
+impl<'input> Parser<'input> {
+    /// Create a parser for `input`, precomputing its source lines.
+    pub fn new(input: &'input str) -> Self {
+        Parser {
+            input,
+            source_lines: util::get_source_lines(input),
+            braces: util::ClosingBraces::default(),
+        }
+    }
+
+    /// The input document of this parser.
+    pub fn input(&self) -> &'input str {
+        self.input
+    }
+
+    /// Parse the input document with the default settings.
+    pub fn parse(&self) -> Result<Element, MWError> {
+        self.parse_with_settings(&GeneralSettings::default())
+    }
+
+    /// Parse the input document and apply the default transformations
+    /// with the given settings.
+    pub fn parse_with_settings(&self, settings: &GeneralSettings) -> Result<Element, MWError> {
+        #[cfg(feature = "ptime")]
+        let starttime = time::precise_time_ns();
+
+        let result = match grammar::document(self.input, &self.source_lines, settings, &self.braces)
+        {
+            Err(e) => Err(error::MWError::ParseError(
+                error::ParseError::with_source_lines(
+                    &e,
+                    &self.source_lines,
+                    settings.friendly_errors,
+                ),
+            )),
+            Ok(r) => Ok(r),
+        }?;
+
+        #[cfg(feature = "ptime")]
+        let parsedtime = time::precise_time_ns();
+
+        let trans_result = apply_transformations(result, settings);
+
+        #[cfg(feature = "ptime")]
+        {
+            eprintln!(
+                "Parse Timer: Parsing took {} ms.",
+                ((parsedtime - starttime) as f64) / 1.0e6
+            );
+            eprintln!(
+                "Parse Timer: Transformation took {} ms.",
+                ((time::precise_time_ns() - parsedtime) as f64) / 1.0e6
+            );
+        }
+
+        trans_result.map_err(error::MWError::TransformationError)
+    }
+}
+
+/// Transformations which keep the block structure, in the order they are applied.
+/// They are shared by `parse` and `parse_inline`, so that both produce the same elements.
+const INLINE_TRANSFORMATIONS: &[fn(Element, &GeneralSettings) -> transformations::TResult] = &[
+    validate_external_refs,
+    collapse_consecutive_text,
+    enumerate_anon_args,
+    tag_functions_to_tags,
+    unknown_tags_to_errors,
+    category_sort_keys,
+    file_link_options,
+    interwiki_links,
+];
+
 fn apply_transformations(
     mut root: Element,
     settings: &GeneralSettings,
 ) -> transformations::TResult {
-    root = validate_external_refs(root, settings)?;
     root = fold_headings_transformation(root, settings)?;
     root = fold_lists_transformation(root, settings)?;
     root = whitespace_paragraphs_to_empty(root, settings)?;
     root = collapse_paragraphs(root, settings)?;
-    root = collapse_consecutive_text(root, settings)?;
-    root = enumerate_anon_args(root, settings)?;
+    apply_inline_transformations(root, settings)
+}
+
+/// Apply the `INLINE_TRANSFORMATIONS`.
+fn apply_inline_transformations(
+    mut root: Element,
+    settings: &GeneralSettings,
+) -> transformations::TResult {
+    for transformation in INLINE_TRANSFORMATIONS {
+        root = transformation(root, settings)?;
+    }
     Ok(root)
 }
@@ -6,9 +6,6 @@
 //! documentation for a description of possible elements of the abstract
 //! syntax tree.
 
-use mediawiki_parser;
-use serde_json;
-use serde_yaml;
 use std::fs;
 use std::io;
 use std::io::prelude::*;
@@ -29,10 +26,14 @@ struct Args {
     /// Ouput the result as JSON
     #[structopt(short = "j", long = "json")]
     pub use_json: bool,
+
+    /// Print element counts and tree depth to stderr
+    #[structopt(short = "s", long = "stats")]
+    pub stats: bool,
 }
 
 /// read contents of a `io::Reader` into a string
-fn read_from_reader(reader: &mut io::Read) -> String {
+fn read_from_reader(reader: &mut dyn io::Read) -> String {
     let mut buffer = io::BufReader::new(reader);
     let mut content = String::new();
     buffer
@@ -64,6 +65,9 @@ fn main() {
     let result = mediawiki_parser::parse(&input);
     match result {
         Ok(r) => {
+            if args.stats {
+                eprint!("{}", mediawiki_parser::stats::tree_stats(&r));
+            }
             if args.use_json {
                 serde_json::to_writer(io::stdout(), &r).expect("could not serialize json!");
             } else {
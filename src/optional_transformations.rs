@@ -0,0 +1,1275 @@
+//! Transformations which are not applied by `parse()`, but may be
+//! applied to the resulting document tree on demand.
+
+use crate::ast::*;
+use crate::default_transformations::GeneralSettings;
+use crate::extract::text_content_vec;
+use crate::transformations::*;
+use crate::util;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// Remove the synthetic list items `fold_lists_transformation` creates
+/// when a list starts with an item deeper than its siblings.
+///
+/// Such an item only wraps a sublist and has the same position as this sublist.
+/// It is replaced by the items of the wrapped sublist. The depth of each item
+/// is kept, so the original nesting can still be reconstructed.
+pub fn flatten_redundant_lists(mut root: Element, settings: &GeneralSettings) -> TResult {
+    fn splice_synthetic_items<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut result = vec![];
+        for child in root_content.drain(..) {
+            if let Element::ListItem(mut item) = child {
+                let is_synthetic = match item.content.first() {
+                    Some(Element::List(ref list)) => {
+                        item.content.len() == 1 && same_source(&list.position, &item.position)
+                    }
+                    _ => false,
+                };
+                if is_synthetic {
+                    if let Some(Element::List(mut list)) = item.content.pop() {
+                        // the spliced items may be synthetic themselves.
+                        let mut inner = splice_synthetic_items(trans, &mut list.content, settings)?;
+                        result.append(&mut inner);
+                    }
+                } else {
+                    result.push(trans(Element::ListItem(item), settings)?);
+                }
+            } else {
+                result.push(trans(child, settings)?);
+            }
+        }
+        Ok(result)
+    }
+
+    if let Element::List { .. } = root {
+        root = recurse_inplace_template(
+            &flatten_redundant_lists,
+            root,
+            settings,
+            &splice_synthetic_items,
+        )?;
+    } else {
+        root = recurse_inplace(&flatten_redundant_lists, root, settings)?;
+    };
+    Ok(root)
+}
+
+/// Remove whitespace-only text elements at the start and end of every content list,
+/// e.g. spaces around a template in a heading caption. Whitespace between other
+/// elements is kept.
+pub fn trim_content_whitespace(root: Element, settings: &GeneralSettings) -> TResult {
+    fn trim_vec<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let is_blank = |e: &Element| match *e {
+            Element::Text(ref t) => util::is_whitespace(&t.text),
+            _ => false,
+        };
+        while root_content.last().map(is_blank).unwrap_or(false) {
+            root_content.pop();
+        }
+        let leading = root_content.iter().take_while(|e| is_blank(e)).count();
+        root_content.drain(..leading);
+        apply_func_drain(trans, root_content, settings)
+    }
+    recurse_inplace_template(&trim_content_whitespace, root, settings, &trim_vec)
+}
+
+/// Number all elements of the tree in document order, starting with 0 for `root`.
+///
+/// The ids only depend on the tree structure, so they are the same for
+/// identical input and can be used to link elements to rendered output.
+pub fn assign_ids(root: Element, _settings: &GeneralSettings) -> TResult {
+    fn number(mut root: Element, counter: &Cell<u64>) -> TResult {
+        *root.get_id_mut() = Some(counter.get());
+        counter.set(counter.get() + 1);
+        // `recurse_inplace` visits the content of headings and the caption of links
+        // before the elements preceding them in the document.
+        match root {
+            Element::Heading(ref mut e) => {
+                e.caption = apply_func_drain(&number, &mut e.caption, counter)?;
+                e.content = apply_func_drain(&number, &mut e.content, counter)?;
+                Ok(root)
+            }
+            Element::InternalReference(ref mut e) => {
+                e.target = apply_func_drain(&number, &mut e.target, counter)?;
+                for option in &mut e.options {
+                    *option = apply_func_drain(&number, option, counter)?;
+                }
+                e.caption = apply_func_drain(&number, &mut e.caption, counter)?;
+                Ok(root)
+            }
+            _ => recurse_inplace(&number, root, counter),
+        }
+    }
+    number(root, &Cell::new(0))
+}
+
+/// Convert html lists (`ul`, `ol` and `dl` tags) to `List` elements,
+/// and their `li`, `dt` and `dd` tags to `ListItem`s.
+///
+/// The depth of an item is the number of html lists it is nested in.
+/// Paragraphs and whitespace between the items of a list are removed.
+/// Other content of a list, like a list nested directly in a list, is added to the
+/// preceding item, or to an item of its own if there is none.
+/// Lists are moved out of the paragraphs they were written in.
+pub fn normalize_html_lists(root: Element, _settings: &GeneralSettings) -> TResult {
+    fn list_items(content: Vec<Element>) -> Vec<Element> {
+        let mut result = vec![];
+        for child in content {
+            match child {
+                Element::Paragraph(par) => result.append(&mut list_items(par.content)),
+                Element::Text(ref t) if util::is_whitespace(&t.text) => (),
+                _ => result.push(child),
+            }
+        }
+        result
+    }
+
+    fn lift_lists(
+        func: &TFuncInplace<usize>,
+        content: &mut Vec<Element>,
+        depth: usize,
+    ) -> TListResult {
+        let content = apply_func_drain(func, content, depth)?;
+        Ok(lift_from_paragraphs(content, &|e| {
+            matches!(*e, Element::List(_))
+        }))
+    }
+
+    fn convert(root: Element, depth: usize) -> TResult {
+        let list = match root {
+            Element::HtmlTag(tag) => tag,
+            _ => return recurse_inplace_template(&convert, root, depth, &lift_lists),
+        };
+        let default_kind = match list.name.to_lowercase().as_str() {
+            "ul" | "dl" => ListItemKind::Unordered,
+            "ol" => ListItemKind::Ordered,
+            _ => {
+                return recurse_inplace_template(
+                    &convert,
+                    Element::HtmlTag(list),
+                    depth,
+                    &lift_lists,
+                )
+            }
+        };
+
+        let mut content = vec![];
+        for child in list_items(list.content) {
+            let kind = match child {
+                Element::HtmlTag(ref item) => match item.name.to_lowercase().as_str() {
+                    "li" => Some(default_kind),
+                    "dt" => Some(ListItemKind::DefinitionTerm),
+                    "dd" => Some(ListItemKind::Definition),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match (child, kind) {
+                (Element::HtmlTag(mut item), Some(kind)) => {
+                    content.push(Element::ListItem(ListItem {
+                        position: item.position,
+                        id: item.id,
+                        depth: depth + 1,
+                        kind,
+                        content: lift_lists(&convert, &mut item.content, depth + 1)?,
+                    }));
+                }
+                (child, _) => {
+                    let child = convert(child, depth + 1)?;
+                    match content.last_mut() {
+                        Some(Element::ListItem(ref mut item)) => item.content.push(child),
+                        _ => content.push(Element::ListItem(ListItem {
+                            position: child.get_position().clone(),
+                            id: None,
+                            depth: depth + 1,
+                            kind: default_kind,
+                            content: vec![child],
+                        })),
+                    }
+                }
+            }
+        }
+        Ok(Element::List(List {
+            position: list.position,
+            id: list.id,
+            content,
+        }))
+    }
+    convert(root, 0)
+}
+
+/// Convert definition terms which are used as pseudo-headings (`; Term`) into headings
+/// of depth `definition_term_heading_depth`.
+///
+/// Only terms of top-level lists without a following definition (`: def`) and
+/// without an inline definition (`;term:def`) are converted. Like for parsed
+/// headings, the following elements become the content of the new heading.
+pub fn definition_terms_to_headings(root: Element, settings: &GeneralSettings) -> TResult {
+    fn is_standalone_term(item: &Element, next: Option<&Element>) -> bool {
+        let term = match *item {
+            Element::ListItem(ref i) if i.kind == ListItemKind::DefinitionTerm && i.depth == 1 => i,
+            _ => return false,
+        };
+        let inline_definition = term.content.iter().any(|e| match *e {
+            Element::Text(ref t) => t.text.contains(':'),
+            _ => false,
+        });
+        let followed_by_definition = match next {
+            Some(Element::ListItem(ref i)) => i.kind == ListItemKind::Definition,
+            _ => false,
+        };
+        !inline_definition && !followed_by_definition
+    }
+
+    // split a list at its standalone terms into lists and new headings.
+    fn split_list(list: List, settings: &GeneralSettings, result: &mut Vec<Element>) {
+        let mut items: Vec<Element> = vec![];
+        let flush = |items: &mut Vec<Element>, result: &mut Vec<Element>| {
+            if let (Some(first), Some(last)) = (items.first(), items.last()) {
+                let position = Span {
+                    start: first.get_position().start.clone(),
+                    end: last.get_position().end.clone(),
+                };
+                let content = std::mem::take(items);
+                result.push(Element::List(List {
+                    position,
+                    id: None,
+                    content,
+                }));
+            }
+        };
+
+        let standalone: Vec<bool> = (0..list.content.len())
+            .map(|i| is_standalone_term(&list.content[i], list.content.get(i + 1)))
+            .collect();
+        if !standalone.contains(&true) {
+            result.push(Element::List(list));
+            return;
+        }
+        for (item, is_standalone) in list.content.into_iter().zip(standalone) {
+            match item {
+                Element::ListItem(term) if is_standalone => {
+                    flush(&mut items, result);
+                    result.push(Element::Heading(Heading {
+                        position: term.position,
+                        id: term.id,
+                        depth: settings.definition_term_heading_depth,
+                        open_markers: None,
+                        close_markers: None,
+                        caption: term.content,
+                        content: vec![],
+                    }));
+                }
+                _ => items.push(item),
+            }
+        }
+        flush(&mut items, result);
+    }
+
+    fn convert_terms<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut split = vec![];
+        for child in root_content.drain(..) {
+            match child {
+                Element::List(list) => split_list(list, settings, &mut split),
+                _ => split.push(child),
+            }
+        }
+        let mut result = fold_section_content(split);
+        apply_func_drain(trans, &mut result, settings)
+    }
+    recurse_inplace_template(
+        &definition_terms_to_headings,
+        root,
+        settings,
+        &convert_terms,
+    )
+}
+
+/// Remove horizontal rules which directly follow another horizontal rule.
+pub fn collapse_horizontal_rules(root: Element, settings: &GeneralSettings) -> TResult {
+    fn dedup_rules<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut result: Vec<Element> = vec![];
+        for child in root_content.drain(..) {
+            if let (Some(Element::HorizontalRule(_)), Element::HorizontalRule(_)) =
+                (result.last(), &child)
+            {
+                continue;
+            }
+            result.push(child);
+        }
+        apply_func_drain(trans, &mut result, settings)
+    }
+    recurse_inplace_template(&collapse_horizontal_rules, root, settings, &dedup_rules)
+}
+
+/// Remove empty or whitespace-only paragraphs next to other block elements, like an
+/// empty paragraph between a list and a table, which would be rendered as a blank line.
+///
+/// Empty paragraphs between two paragraphs separate them and are kept.
+pub fn remove_blank_paragraphs(root: Element, settings: &GeneralSettings) -> TResult {
+    fn is_blank(elem: &Element) -> bool {
+        match *elem {
+            Element::Paragraph(ref par) => par.content.iter().all(|child| match *child {
+                Element::Text(ref text) => util::is_whitespace(&text.text),
+                _ => false,
+            }),
+            _ => false,
+        }
+    }
+    fn is_other_block(elem: Option<&Element>) -> bool {
+        elem.is_some_and(|e| e.is_block() && !matches!(e, Element::Paragraph(_)))
+    }
+    fn remove_blanks<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut result: Vec<Element> = vec![];
+        let mut blanks: Vec<Element> = vec![];
+        for child in root_content.drain(..) {
+            if is_blank(&child) {
+                blanks.push(child);
+                continue;
+            }
+            // a run of blank paragraphs is removed if a block element precedes or follows it.
+            if !(is_other_block(result.last()) || is_other_block(Some(&child))) {
+                result.append(&mut blanks);
+            }
+            blanks.clear();
+            result.push(child);
+        }
+        if !is_other_block(result.last()) {
+            result.append(&mut blanks);
+        }
+        apply_func_drain(trans, &mut result, settings)
+    }
+    recurse_inplace_template(&remove_blank_paragraphs, root, settings, &remove_blanks)
+}
+
+/// Move the elements following a heading into its content, up to the next heading
+/// of the same or a lower depth. Deeper headings are nested as well.
+///
+/// Parsed documents are already structured like this, but trees which are built or
+/// transformed with flat headings are turned into a document outline.
+pub fn fold_sections(root: Element, settings: &GeneralSettings) -> TResult {
+    fn fold_vec<'a>(
+        trans: &TFuncInplace<&'a GeneralSettings>,
+        root_content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let mut result = fold_section_content(std::mem::take(root_content));
+        apply_func_drain(trans, &mut result, settings)
+    }
+    recurse_inplace_template(&fold_sections, root, settings, &fold_vec)
+}
+
+/// Split a document into its top-level sections, e.g. to edit sections separately.
+///
+/// Every section is a `Document` containing its heading, paired with the text of the
+/// heading caption. Elements before the first heading form the lead section, which is
+/// always the first entry and has no title. Flat headings are folded like in `fold_sections`.
+///
+/// A section spans the source up to the start of the next section, including subsections.
+pub fn split_sections(root: Element) -> Vec<(Option<String>, Element)> {
+    let root = fold_sections(root, &GeneralSettings::default()).expect("folding sections failed!");
+    let (position, content) = match root {
+        Element::Document(doc) => (doc.position, doc.content),
+        other => return vec![(None, other)],
+    };
+
+    let mut sections: Vec<(Option<String>, Vec<Element>)> = vec![(None, vec![])];
+    for elem in content {
+        if let Element::Heading(ref heading) = elem {
+            let title = text_content_vec(&heading.caption).trim().to_string();
+            sections.push((Some(title), vec![]));
+        }
+        if let Some(section) = sections.last_mut() {
+            section.1.push(elem);
+        }
+    }
+    let mut ends: Vec<Position> = sections[1..]
+        .iter()
+        .map(|s| s.1[0].get_position().start.clone())
+        .collect();
+    ends.push(position.end.clone());
+    let mut start = position.start;
+    sections
+        .into_iter()
+        .zip(ends)
+        .map(|((title, content), end)| {
+            let start = std::mem::replace(&mut start, end.clone());
+            let doc = Document {
+                position: Span { start, end },
+                id: None,
+                content,
+            };
+            (title, Element::Document(doc))
+        })
+        .collect()
+}
+
+/// Replace templates by the given elements, e.g. for a preview without access to the
+/// template pages. Arguments of the templates are ignored.
+///
+/// Templates are found by title, so a replacement for `Foo bar` also replaces
+/// `{{foo_bar}}` and `{{Template:Foo bar}}`. Templates in the replacements are kept as they are.
+pub fn substitute_templates(
+    root: Element,
+    replacements: &HashMap<String, Vec<Element>>,
+) -> Element {
+    type Replacements<'a> = &'a HashMap<String, &'a Vec<Element>>;
+    fn substitute(root: Element, replacements: Replacements) -> TResult {
+        recurse_inplace_template(&substitute, root, replacements, &substitute_vec)
+    }
+    fn substitute_vec<'a>(
+        trans: &TFuncInplace<Replacements<'a>>,
+        root_content: &mut Vec<Element>,
+        replacements: Replacements<'a>,
+    ) -> TListResult {
+        let mut result = vec![];
+        for child in root_content.drain(..) {
+            let replacement = match child {
+                Element::Template(ref template) => template
+                    .name_str()
+                    .and_then(|name| replacements.get(&normalize_template_title(&name))),
+                _ => None,
+            };
+            match replacement {
+                Some(elements) => result.extend(elements.iter().cloned()),
+                None => result.push(trans(child, replacements)?),
+            }
+        }
+        Ok(result)
+    }
+    let replacements: HashMap<String, &Vec<Element>> = replacements
+        .iter()
+        .map(|(name, elements)| (normalize_template_title(name), elements))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    substitute(root, &replacements).expect("substituting templates failed!")
+}
+
+/// Set the depth of every heading to its level in the document outline, starting at 1.
+/// A document whose shallowest heading is `=== Title ===` then has headings of depth 1,
+/// like `<h1>` in html. Depths are capped at 6.
+///
+/// The outline is given by the nesting of headings, as done by `parse()` or `fold_sections`.
+/// The number of markers written in the source is kept in `open_markers` and `close_markers`.
+pub fn normalize_heading_depths(root: Element, _settings: &GeneralSettings) -> TResult {
+    fn normalize(mut root: Element, level: usize) -> TResult {
+        if let Element::Heading(ref mut heading) = root {
+            heading.depth = level.min(6);
+            return recurse_inplace(&normalize, root, level + 1);
+        }
+        recurse_inplace(&normalize, root, level)
+    }
+    normalize(root, 1)
+}
+
+/// Wrap tables with a caption and thumbnail images with a caption in a `Figure`,
+/// so that both are captioned the same way. The caption is moved from the
+/// table or image to the figure.
+///
+/// Images are thumbnails if they have the option `thumb`, `thumbnail`, `frame` or `framed`.
+/// Figures are block elements, so the paragraphs they were written in are split around them.
+pub fn wrap_figures(root: Element, settings: &GeneralSettings) -> TResult {
+    fn lift_figures<'a>(
+        func: &TFuncInplace<&'a GeneralSettings>,
+        content: &mut Vec<Element>,
+        settings: &'a GeneralSettings,
+    ) -> TListResult {
+        let content = apply_func_drain(func, content, settings)?;
+        Ok(lift_from_paragraphs(content, &|e| {
+            matches!(*e, Element::Figure(_))
+        }))
+    }
+
+    let mut root = recurse_inplace_template(&wrap_figures, root, settings, &lift_figures)?;
+    let (position, caption) = match root {
+        Element::Table(ref mut table) if !table.caption.is_empty() => {
+            (table.position.clone(), std::mem::take(&mut table.caption))
+        }
+        Element::InternalReference(ref mut iref)
+            if iref.namespace() == Some("File")
+                && is_thumbnail(iref)
+                && !iref.caption.is_empty() =>
+        {
+            (iref.position.clone(), std::mem::take(&mut iref.caption))
+        }
+        _ => return Ok(root),
+    };
+    Ok(Element::Figure(Figure {
+        position,
+        id: None,
+        content: vec![root],
+        caption,
+    }))
+}
+
+/// Convert the different forms of source code into `Code` elements:
+/// inline `<code>` tags, as well as `<pre>` tags, indented preformatted text and
+/// `<syntaxhighlight>` (or `<source>`) tags, which form code blocks.
+///
+/// `<syntaxhighlight>` and `<source>` are only converted if they are parsed as extension
+/// tags, so they must be added to `raw_extension_tags` of the settings used for parsing.
+/// The language of code blocks is taken from the `lang` attribute of `<syntaxhighlight>`.
+/// An `inline` attribute makes `<syntaxhighlight>` inline code.
+///
+/// `<code>`, `<pre>` and preformatted text containing other elements than text,
+/// like formatting, links or templates, are kept as they are, as their content is rendered.
+pub fn normalize_code(root: Element, settings: &GeneralSettings) -> TResult {
+    let code = match root {
+        Element::Formatted(ref fmt)
+            if matches!(fmt.markup, MarkupType::Code | MarkupType::Preformatted) =>
+        {
+            match verbatim_text(&fmt.content) {
+                Some(text) => Code {
+                    position: fmt.position.clone(),
+                    id: fmt.id,
+                    inline: fmt.markup == MarkupType::Code,
+                    language: None,
+                    text,
+                },
+                None => return recurse_inplace(&normalize_code, root, settings),
+            }
+        }
+        Element::ExtensionTag(ref tag)
+            if ["syntaxhighlight", "source"].contains(&tag.name.to_lowercase().as_str()) =>
+        {
+            let attribute = |key: &str| {
+                tag.attributes
+                    .iter()
+                    .find(|attr| attr.key.trim().eq_ignore_ascii_case(key))
+            };
+            Code {
+                position: tag.position.clone(),
+                id: tag.id,
+                inline: attribute("inline").is_some(),
+                language: attribute("lang").map(|attr| attr.value.trim().to_string()),
+                text: tag.body.clone(),
+            }
+        }
+        _ => return recurse_inplace(&normalize_code, root, settings),
+    };
+    Ok(Element::Code(code))
+}
+
+/// Use typographic characters in text: curly quotes for straight quotes, en dashes for `--`,
+/// em dashes for `---` and an ellipsis for `...`.
+///
+/// Code, preformatted text, nowiki and math are kept as they are, as well as
+/// template names and link targets. A quote opens at the start of a block, list item,
+/// table cell or template argument, or after whitespace or an opening bracket.
+/// Otherwise it closes, e.g. after formatted text, a link or a template.
+pub fn typographic_text(root: Element, _settings: &GeneralSettings) -> TResult {
+    // a skipped element counts as a word, so that a following quote closes.
+    const WORD: Option<char> = Some('w');
+
+    fn convert(mut root: Element, previous: &Cell<Option<char>>) -> TResult {
+        if !root.is_inline() {
+            previous.set(None);
+        }
+        match root {
+            Element::Text(ref mut text) => text.text = typographic(&text.text, previous),
+            Element::Formatted(ref fmt)
+                if matches!(
+                    fmt.markup,
+                    MarkupType::Code
+                        | MarkupType::Preformatted
+                        | MarkupType::NoWiki
+                        | MarkupType::Math
+                ) =>
+            {
+                previous.set(WORD)
+            }
+            Element::Parameter(_) => previous.set(WORD),
+            Element::Heading(ref mut heading) => {
+                heading.caption = apply_func_drain(&convert, &mut heading.caption, previous)?;
+                heading.content = apply_func_drain(&convert, &mut heading.content, previous)?;
+            }
+            Element::Template(ref mut template) => {
+                template.content = apply_func_drain(&convert, &mut template.content, previous)?;
+                previous.set(WORD);
+            }
+            Element::InternalReference(ref mut iref) => {
+                let before = previous.get();
+                for option in &mut iref.options {
+                    previous.set(None);
+                    *option = apply_func_drain(&convert, option, previous)?;
+                }
+                previous.set(before);
+                iref.caption = apply_func_drain(&convert, &mut iref.caption, previous)?;
+                if iref.caption.is_empty() {
+                    previous.set(WORD);
+                }
+            }
+            _ => return recurse_inplace(&convert, root, previous),
+        };
+        Ok(root)
+    }
+    convert(root, &Cell::new(None))
+}
+
+/// Replace quotes, dashes and ellipses of a text by typographic characters.
+/// `previous` is the character preceding the text, it is set to the last character.
+fn typographic(text: &str, previous: &Cell<Option<char>>) -> String {
+    let text = text
+        .replace("---", "—")
+        .replace("--", "–")
+        .replace("...", "…");
+    let mut result = String::with_capacity(text.len());
+    let mut previous_char = previous.get();
+    for c in text.chars() {
+        let opens = previous_char.is_none_or(|p| p.is_whitespace() || "([{–—".contains(p));
+        result.push(match (c, opens) {
+            ('"', true) => '“',
+            ('"', false) => '”',
+            ('\'', true) => '‘',
+            ('\'', false) => '’',
+            _ => c,
+        });
+        previous_char = Some(c);
+    }
+    previous.set(previous_char);
+    result
+}
+
+/// Get the text of code content, keeping all whitespace.
+fn verbatim_text(content: &[Element]) -> Option<String> {
+    content
+        .iter()
+        .map(|elem| match *elem {
+            Element::Text(ref text) => Some(text.text.clone()),
+            Element::Paragraph(ref par) => verbatim_text(&par.content),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Do two spans cover the same source? "Any" positions never match,
+/// as they are equal to every position.
+fn same_source(a: &Span, b: &Span) -> bool {
+    let offsets = |span: &Span| {
+        if span.start.is_any() || span.end.is_any() {
+            None
+        } else {
+            Some((span.start.offset, span.end.offset))
+        }
+    };
+    offsets(a).is_some() && offsets(a) == offsets(b)
+}
+
+/// Is an image displayed as a thumbnail with its caption?
+fn is_thumbnail(iref: &InternalReference) -> bool {
+    iref.options.iter().any(|option| {
+        let text = crate::extract::text_content_vec(option);
+        let name = text.split('=').next().unwrap_or_default().trim();
+        ["thumb", "thumbnail", "frame", "framed"]
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Nest the elements of a content list into the preceding headings.
+fn fold_section_content(content: Vec<Element>) -> Vec<Element> {
+    let mut result = vec![];
+    // the currently open headings, with increasing depth.
+    let mut open: Vec<Heading> = vec![];
+
+    fn close(heading: Heading, open: &mut [Heading], result: &mut Vec<Element>) {
+        match open.last_mut() {
+            Some(parent) => parent.content.push(Element::Heading(heading)),
+            None => result.push(Element::Heading(heading)),
+        }
+    }
+
+    for child in content {
+        match child {
+            Element::Heading(heading) => {
+                while open
+                    .last()
+                    .map(|h| h.depth >= heading.depth)
+                    .unwrap_or(false)
+                {
+                    let done = open.pop().unwrap();
+                    close(done, &mut open, &mut result);
+                }
+                open.push(heading);
+            }
+            _ => match open.last_mut() {
+                Some(heading) => heading.content.push(child),
+                None => result.push(child),
+            },
+        }
+    }
+    while let Some(done) = open.pop() {
+        close(done, &mut open, &mut result);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traversion::Traversion;
+    use crate::{parse, Parser};
+    use std::io;
+
+    fn list_depths(list: &Element) -> Vec<usize> {
+        match *list {
+            Element::List(ref l) => l
+                .content
+                .iter()
+                .map(|item| match *item {
+                    Element::ListItem(ref i) => i.depth,
+                    _ => panic!("list contains a non-list item: {:?}", item),
+                })
+                .collect(),
+            _ => panic!("not a list: {:?}", list),
+        }
+    }
+
+    fn first_child(root: &Element) -> &Element {
+        match *root {
+            Element::Document(ref d) => &d.content[0],
+            _ => panic!("not a document: {:?}", root),
+        }
+    }
+
+    #[test]
+    fn flatten_single_branch_list() {
+        let input = "*** deep\n** middle\n* shallow\n";
+        let settings = GeneralSettings::default();
+        let folded = parse(input).unwrap();
+        assert_eq!(list_depths(first_child(&folded)), vec![1, 1]);
+
+        let flat = flatten_redundant_lists(folded, &settings).unwrap();
+        assert_eq!(list_depths(first_child(&flat)), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn flatten_keeps_written_items() {
+        let input = "* first\n** nested\n* second\n";
+        let folded = parse(input).unwrap();
+        let flat = flatten_redundant_lists(folded.clone(), &GeneralSettings::default()).unwrap();
+        assert_eq!(folded, flat);
+    }
+
+    #[test]
+    fn flatten_keeps_items_without_positions() {
+        // a generated item wrapping a sublist, without source positions.
+        let item = |depth, content| {
+            Element::ListItem(ListItem {
+                depth,
+                content,
+                ..Default::default()
+            })
+        };
+        let sublist = Element::List(List {
+            position: Span::any(),
+            id: None,
+            content: vec![item(2, vec![])],
+        });
+        let list = Element::List(List {
+            position: Span::any(),
+            id: None,
+            content: vec![item(1, vec![sublist])],
+        });
+        let flat = flatten_redundant_lists(list, &GeneralSettings::default()).unwrap();
+        assert_eq!(list_depths(&flat), vec![1]);
+    }
+
+    #[test]
+    fn trim_heading_caption() {
+        let root = parse("== {{title}} ''a'' ==\ntext").unwrap();
+        let trimmed = trim_content_whitespace(root, &GeneralSettings::default()).unwrap();
+        let heading = match *first_child(&trimmed) {
+            Element::Heading(ref h) => h.clone(),
+            ref e => panic!("not a heading: {:?}", e),
+        };
+        let caption: Vec<_> = heading
+            .caption
+            .iter()
+            .map(|e| e.get_variant_name())
+            .collect();
+        assert_eq!(caption, vec!["Template", "Text", "Formatted"]);
+    }
+
+    #[test]
+    fn ids_are_sequential() {
+        struct IdCollector<'e> {
+            path: Vec<&'e Element>,
+            ids: Vec<Option<u64>>,
+        }
+
+        impl<'e> Traversion<'e, ()> for IdCollector<'e> {
+            fn path_push(&mut self, elem: &'e Element) {
+                self.path.push(elem);
+            }
+            fn path_pop(&mut self) -> Option<&'e Element> {
+                self.path.pop()
+            }
+            fn get_path(&self) -> &Vec<&'e Element> {
+                &self.path
+            }
+            fn work(
+                &mut self,
+                root: &'e Element,
+                _: (),
+                _: &mut dyn io::Write,
+            ) -> io::Result<bool> {
+                self.ids.push(root.get_id());
+                Ok(true)
+            }
+        }
+
+        let input = "== {{title|a=[[File:x.png|thumb|''caption'']]}} ==\n{|\n| cell\n|}\n* item\n";
+        let settings = GeneralSettings::default();
+        let root = assign_ids(parse(input).unwrap(), &settings).unwrap();
+        assert_eq!(root, assign_ids(parse(input).unwrap(), &settings).unwrap());
+
+        let mut collector = IdCollector {
+            path: vec![],
+            ids: vec![],
+        };
+        collector.run(&root, (), &mut vec![]).unwrap();
+        let expected: Vec<_> = (0..collector.ids.len() as u64).map(Some).collect();
+        assert!(expected.len() > 10);
+        assert_eq!(collector.ids, expected);
+    }
+
+    #[test]
+    fn normalize_nested_html_list() {
+        let input = "<ul>\n<li>one</li>\n<li>two\n<ul><li>nested</li></ul></li>\n</ul>\n";
+        let root = parse(input).unwrap();
+        let root = normalize_html_lists(root, &GeneralSettings::default()).unwrap();
+        let list = first_child(&root).clone();
+        assert_eq!(list_depths(&list), vec![1, 1]);
+
+        let second = match list {
+            Element::List(ref l) => l.content[1].clone(),
+            _ => unreachable!(),
+        };
+        let nested = match second {
+            Element::ListItem(ref i) => {
+                assert_eq!(i.kind, ListItemKind::Unordered);
+                i.content.last().unwrap().clone()
+            }
+            ref e => panic!("not a list item: {:?}", e),
+        };
+        assert_eq!(list_depths(&nested), vec![2]);
+    }
+
+    #[test]
+    fn normalize_list_in_list() {
+        let input = "<ul><ul><li>b</li></ul></ul>\n";
+        let root = normalize_html_lists(parse(input).unwrap(), &GeneralSettings::default());
+        let root = root.unwrap();
+        assert_eq!(crate::validate::validate(&root), vec![]);
+        let list = first_child(&root);
+        assert_eq!(list_depths(list), vec![1]);
+        let wrapper = match *list {
+            Element::List(ref l) => l.content[0].as_list_item().unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(list_depths(&wrapper.content[0]), vec![2]);
+    }
+
+    #[test]
+    fn normalize_list_in_paragraph() {
+        let input = "before <ul><li>a</li></ul> after\n";
+        let root = normalize_html_lists(parse(input).unwrap(), &GeneralSettings::default());
+        let root = root.unwrap();
+        assert_eq!(crate::validate::validate(&root), vec![]);
+        let content = match root {
+            Element::Document(ref d) => &d.content,
+            _ => unreachable!(),
+        };
+        let names: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+        assert_eq!(names, vec!["Paragraph", "List", "Paragraph"]);
+        assert_eq!(text_content_vec(&content[0..1]).trim(), "before");
+    }
+
+    #[test]
+    fn definition_terms_as_headings() {
+        let input = "; Term\ntext\n;term:def\n; a\n: b\n== Next ==\nx\n";
+        let root = parse(input).unwrap();
+        let pairs = match root {
+            Element::Document(ref d) => d.content[2].clone(),
+            _ => unreachable!(),
+        };
+        let root = definition_terms_to_headings(root, &GeneralSettings::default()).unwrap();
+        let content = match root {
+            Element::Document(ref d) => &d.content,
+            _ => unreachable!(),
+        };
+        assert_eq!(content.len(), 2);
+
+        let term = match content[0] {
+            Element::Heading(ref h) => h,
+            ref e => panic!("not a heading: {:?}", e),
+        };
+        assert_eq!(term.depth, 3);
+        assert_eq!(crate::extract::text_content_vec(&term.caption), "Term");
+        // the term and definition pairs are kept.
+        assert_eq!(term.content.len(), 2);
+        assert_eq!(term.content[1], pairs);
+
+        match content[1] {
+            Element::Heading(ref h) => assert_eq!(h.depth, 2),
+            ref e => panic!("not a heading: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn fold_flat_sections() {
+        let heading = |depth| {
+            Element::Heading(Heading {
+                depth,
+                ..Default::default()
+            })
+        };
+        let paragraph = || Element::Paragraph(Paragraph::default());
+        let root = Element::Document(Document {
+            content: vec![
+                paragraph(),
+                heading(2),
+                paragraph(),
+                heading(3),
+                paragraph(),
+                heading(2),
+            ],
+            ..Default::default()
+        });
+
+        let folded = fold_sections(root, &GeneralSettings::default()).unwrap();
+        let content = &folded.as_document().unwrap().content;
+        let variants: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+        assert_eq!(variants, vec!["Paragraph", "Heading", "Heading"]);
+
+        let section = content[1].as_heading().unwrap();
+        assert_eq!(section.content[0], paragraph());
+        let subsection = section.content[1].as_heading().unwrap();
+        assert_eq!(subsection.depth, 3);
+        assert_eq!(subsection.content, vec![paragraph()]);
+
+        let parsed = parse("== A ==\ntext\n=== B ===\nmore\n== C ==\n").unwrap();
+        assert_eq!(
+            fold_sections(parsed.clone(), &GeneralSettings::default()).unwrap(),
+            parsed
+        );
+    }
+
+    #[test]
+    fn substitute_one_template() {
+        let replacement = vec![Element::Text(Text {
+            position: Span::any(),
+            id: None,
+            text: "replaced".into(),
+        })];
+        let replacements: HashMap<_, _> = vec![("Greeting".to_string(), replacement)]
+            .into_iter()
+            .collect();
+        let root = parse("a {{greeting|name=x}} b {{other|{{Template:Greeting}}}}").unwrap();
+        let root = substitute_templates(root, &replacements);
+        let par = &root.as_document().unwrap().content[0];
+        let content = &par.as_paragraph().unwrap().content;
+        let variants: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+        assert_eq!(variants, vec!["Text", "Text", "Text", "Template"]);
+        assert_eq!(content[1].as_text().unwrap().text, "replaced");
+
+        let other = content[3].as_template().unwrap();
+        let arg = other.content[0].as_template_argument().unwrap();
+        assert_eq!(arg.value[0].as_text().unwrap().text, "replaced");
+    }
+
+    #[test]
+    fn sections_as_documents() {
+        let input = "intro\n\n== A ==\ntext\n=== A1 ===\nmore\n== B ==\n* item\n== C ==\n";
+        let sections = split_sections(parse(input).unwrap());
+        let titles: Vec<_> = sections.iter().map(|s| s.0.as_deref()).collect();
+        assert_eq!(titles, vec![None, Some("A"), Some("B"), Some("C")]);
+
+        let source: Vec<_> = sections
+            .iter()
+            .map(|(_, doc)| {
+                let span = doc.get_position();
+                &input[span.start.offset..span.end.offset]
+            })
+            .collect();
+        assert_eq!(
+            source,
+            vec![
+                "intro\n\n",
+                "== A ==\ntext\n=== A1 ===\nmore\n",
+                "== B ==\n* item\n",
+                "== C ==\n"
+            ]
+        );
+        let section = sections[1].1.as_document().unwrap();
+        assert_eq!(section.content.len(), 1);
+        let heading = section.content[0].as_heading().unwrap();
+        assert_eq!(heading.content[1].get_variant_name(), "Heading");
+
+        let lead = split_sections(parse("== Only ==\n").unwrap());
+        assert_eq!(lead[0].1.as_document().unwrap().content, vec![]);
+        assert_eq!(lead.len(), 2);
+    }
+
+    #[test]
+    fn heading_depths_from_outline() {
+        fn depths(root: &Element, result: &mut Vec<(String, usize)>) {
+            if let Element::Heading(ref heading) = *root {
+                let caption = crate::extract::text_content_vec(&heading.caption);
+                result.push((caption.trim().to_string(), heading.depth));
+            }
+            let children = match *root {
+                Element::Document(ref doc) => &doc.content,
+                Element::Heading(ref heading) => &heading.content,
+                _ => return,
+            };
+            for child in children {
+                depths(child, result);
+            }
+        }
+
+        let input = "=== A ===\n===== A1 =====\n====== A1a ======\n=== B ===\n==== B1 ====\n";
+        let root = parse(input).unwrap();
+        let root = normalize_heading_depths(root, &GeneralSettings::default()).unwrap();
+        let mut result = vec![];
+        depths(&root, &mut result);
+        let result: Vec<_> = result.iter().map(|(c, d)| (c.as_str(), *d)).collect();
+        assert_eq!(
+            result,
+            vec![("A", 1), ("A1", 2), ("A1a", 3), ("B", 1), ("B1", 2)]
+        );
+    }
+
+    #[test]
+    fn figures_with_captions() {
+        let input = "{|\n|+ The caption\n| cell\n|}\n{|\n| plain\n|}\n\
+                     [[File:x.png|thumb|An image]] [[File:y.png|An inline image]]\n";
+        let root = wrap_figures(parse(input).unwrap(), &GeneralSettings::default()).unwrap();
+        let content = &root.as_document().unwrap().content;
+
+        let figure = content[0].as_figure().expect("table not wrapped!");
+        assert_eq!(
+            crate::extract::text_content_vec(&figure.caption),
+            "The caption"
+        );
+        let table = figure.content[0].as_table().unwrap();
+        assert!(table.caption.is_empty());
+        assert_eq!(&figure.position, content[0].get_position());
+        assert!(content[1].as_table().is_some());
+
+        let figure = content[2].as_figure().expect("thumbnail not wrapped!");
+        assert_eq!(
+            crate::extract::text_content_vec(&figure.caption),
+            "An image"
+        );
+        assert!(figure.content[0]
+            .as_internal_reference()
+            .unwrap()
+            .caption
+            .is_empty());
+        let images = &content[3].as_paragraph().unwrap().content;
+        let inline = images.last().unwrap().as_internal_reference().unwrap();
+        assert!(!inline.caption.is_empty());
+        assert_eq!(content.len(), 4);
+    }
+
+    #[test]
+    fn code_forms() {
+        let input = "Run <code>cargo  test</code>.\n<pre>\nfn a() {}\n</pre>\n \
+                     indented code\n<syntaxhighlight lang=\"rust\">\nfn main() {}\n</syntaxhighlight>\n \
+                     indented ''formatted'' code\n";
+        let mut settings = GeneralSettings::default();
+        settings.raw_extension_tags.insert("syntaxhighlight".into());
+        let root = Parser::new(input).parse_with_settings(&settings).unwrap();
+        let root = normalize_code(root, &settings).unwrap();
+        fn collect(elem: &Element, result: &mut Vec<Code>) {
+            match *elem {
+                Element::Code(ref code) => result.push(code.clone()),
+                Element::Document(ref doc) => doc.content.iter().for_each(|e| collect(e, result)),
+                Element::Paragraph(ref par) => par.content.iter().for_each(|e| collect(e, result)),
+                _ => (),
+            }
+        }
+        let mut codes = vec![];
+        collect(&root, &mut codes);
+        let codes: Vec<_> = codes
+            .iter()
+            .map(|c| (c.inline, c.language.as_deref(), c.text.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (true, None, "cargo  test"),
+                (false, None, "\nfn a() {}\n"),
+                (false, None, "indented code"),
+                (false, Some("rust"), "\nfn main() {}\n"),
+            ]
+        );
+        let content = &root.as_document().unwrap().content;
+        let formatted = content.last().unwrap().as_formatted().unwrap();
+        assert_eq!(formatted.markup, MarkupType::Preformatted);
+    }
+
+    #[test]
+    fn collapse_consecutive_rules() {
+        let root = parse("text\n----\n----\n------\nmore\n----\n").unwrap();
+        let variants = |root: &Element| -> Vec<String> {
+            root.as_document()
+                .unwrap()
+                .content
+                .iter()
+                .map(|e| e.get_variant_name().to_string())
+                .collect()
+        };
+        assert_eq!(
+            variants(&root),
+            vec![
+                "Paragraph",
+                "HorizontalRule",
+                "HorizontalRule",
+                "HorizontalRule",
+                "Paragraph",
+                "HorizontalRule"
+            ]
+        );
+        let collapsed = collapse_horizontal_rules(root, &GeneralSettings::default()).unwrap();
+        assert_eq!(
+            variants(&collapsed),
+            vec!["Paragraph", "HorizontalRule", "Paragraph", "HorizontalRule"]
+        );
+    }
+
+    #[test]
+    fn code_with_links_is_kept() {
+        let settings = GeneralSettings::default();
+        // the content of `<code>` is verbatim text.
+        let root = normalize_code(parse("<code>see [[Foo]]</code>\n").unwrap(), &settings).unwrap();
+        let par = root.as_document().unwrap().content[0]
+            .as_paragraph()
+            .unwrap();
+        assert_eq!(par.content[0].as_code().unwrap().text, "see [[Foo]]");
+
+        let root = parse(" see [[Foo]] {{t}}\n").unwrap();
+        let normalized = normalize_code(root.clone(), &settings).unwrap();
+        assert_eq!(normalized, root);
+    }
+
+    #[test]
+    fn typographic_quotes_and_dashes() {
+        let settings = GeneralSettings::default();
+        let root = parse(
+            "\"Quoted\" and 'single' -- it's 1990--2000 --- wait... (\"x\")\n\n\
+             ''\"emphasis\"'' [[Don't|a \"b\"]] {{Don't|\"c\"}}\n",
+        )
+        .unwrap();
+        let root = typographic_text(root, &settings).unwrap();
+        let content = &root.as_document().unwrap().content;
+        assert_eq!(
+            text_content_vec(&content[..1]),
+            "“Quoted” and ‘single’ – it’s 1990–2000 — wait… (“x”)"
+        );
+        let second = content[1].as_paragraph().unwrap();
+        let iref = second.content[2].as_internal_reference().unwrap();
+        assert_eq!(text_content_vec(&iref.target), "Don't");
+        assert_eq!(text_content_vec(&iref.caption), "a “b”");
+        let template = second.content[4].as_template().unwrap();
+        assert_eq!(template.name_str().as_deref(), Some("Don't"));
+        assert_eq!(
+            text_content_vec(&template.content[0].as_template_argument().unwrap().value),
+            "“c”"
+        );
+        assert_eq!(text_content_vec(&second.content[..1]), "“emphasis”");
+    }
+
+    #[test]
+    fn typographic_quotes_after_formatting() {
+        let root = parse("''a''\"b\" [[c]]'s \"d\"\n\n\"e\"\n").unwrap();
+        let root = typographic_text(root, &GeneralSettings::default()).unwrap();
+        let content = &root.as_document().unwrap().content;
+        assert_eq!(text_content_vec(&content[..1]), "a”b” c’s “d”");
+        assert_eq!(text_content_vec(&content[1..]), "“e”");
+
+        let root = parse("== \"a\" ==\nb\n").unwrap();
+        let root = typographic_text(root, &GeneralSettings::default()).unwrap();
+        let heading = root.as_document().unwrap().content[0].as_heading().unwrap();
+        assert_eq!(text_content_vec(&heading.caption), "“a”");
+    }
+
+    #[test]
+    fn typographic_text_keeps_code() {
+        let input = "<code>a -- \"b\"</code> <nowiki>'c'...</nowiki>\n \"indented\" -- code\n\
+                     <pre>x --- 'y'</pre>\n<math>a'--b</math>\n";
+        let root = parse(input).unwrap();
+        let typographic = typographic_text(root.clone(), &GeneralSettings::default()).unwrap();
+        assert_eq!(typographic, root);
+    }
+
+    #[test]
+    fn blank_paragraphs_between_blocks() {
+        let blank = |text: &str| {
+            Element::Paragraph(Paragraph {
+                content: vec![Element::Text(Text {
+                    text: text.into(),
+                    ..Default::default()
+                })],
+                ..Default::default()
+            })
+        };
+        let parsed = parse("* item\n{|\n| cell\n|}\nfirst\n\nsecond\n").unwrap();
+        let mut content = parsed.as_document().unwrap().content.clone();
+        content.insert(1, blank(" "));
+        content.insert(2, Element::Paragraph(Paragraph::default()));
+        content.insert(4, blank(""));
+        content.insert(6, blank("\t"));
+        content.push(blank(" "));
+        let root = Element::Document(Document {
+            content,
+            ..Default::default()
+        });
+
+        let cleaned = remove_blank_paragraphs(root, &GeneralSettings::default()).unwrap();
+        let variants: Vec<_> = cleaned
+            .as_document()
+            .unwrap()
+            .content
+            .iter()
+            .map(|e| e.get_variant_name())
+            .collect();
+        assert_eq!(
+            variants,
+            vec![
+                "List",
+                "Table",
+                "Paragraph",
+                "Paragraph",
+                "Paragraph",
+                "Paragraph"
+            ]
+        );
+        let content = &cleaned.as_document().unwrap().content;
+        assert_eq!(content[3], blank("\t"));
+        assert_eq!(content[5], blank(" "));
+    }
+}
@@ -0,0 +1,396 @@
+//! Conversion of the document tree to the JSON representation of pandoc's AST,
+//! which can be read by pandoc with `pandoc -f json`.
+//!
+//! Elements without a pandoc equivalent, like templates, comments,
+//! behavior switches and category assignments, are omitted.
+
+use crate::ast::*;
+use crate::extract::text_content_vec;
+use serde_json::{json, Value};
+
+/// The version of the pandoc AST this module produces.
+pub const PANDOC_API_VERSION: [u32; 3] = [1, 23, 1];
+
+/// Convert a document tree to a pandoc JSON document.
+pub fn to_pandoc_json(root: &Element) -> Value {
+    json!({
+        "pandoc-api-version": PANDOC_API_VERSION,
+        "meta": {},
+        "blocks": blocks(std::slice::from_ref(root)),
+    })
+}
+
+/// An empty pandoc attribute (identifier, classes, key-value pairs).
+fn no_attr() -> Value {
+    json!(["", [], []])
+}
+
+/// Attributes of code, with the language as class.
+fn code_attr(code: &Code) -> Value {
+    json!(["", code.language.iter().collect::<Vec<_>>(), []])
+}
+
+/// Convert elements to pandoc blocks. Consecutive inline elements are wrapped
+/// in a `Plain` block.
+fn blocks(elems: &[Element]) -> Vec<Value> {
+    let mut result = vec![];
+    let mut pending: Vec<Element> = vec![];
+    let flush = |pending: &mut Vec<Element>, result: &mut Vec<Value>| {
+        let content = trim_spaces(inlines(pending));
+        if !content.is_empty() {
+            result.push(json!({"t": "Plain", "c": content}));
+        }
+        pending.clear();
+    };
+    for elem in elems {
+        if elem.is_block() || is_container(elem) {
+            flush(&mut pending, &mut result);
+            result.extend(block(elem));
+        } else {
+            pending.push(elem.clone());
+        }
+    }
+    flush(&mut pending, &mut result);
+    result
+}
+
+/// Elements which are neither blocks nor inline, but contain blocks.
+fn is_container(elem: &Element) -> bool {
+    matches!(*elem, Element::Document(_) | Element::Error(_))
+}
+
+/// Convert a block element to pandoc blocks.
+fn block(elem: &Element) -> Vec<Value> {
+    match *elem {
+        Element::Document(ref doc) => blocks(&doc.content),
+        Element::Heading(ref heading) => {
+            let mut result = vec![
+                json!({"t": "Header", "c": [heading.depth, no_attr(), trim_spaces(inlines(&heading.caption))]}),
+            ];
+            result.extend(blocks(&heading.content));
+            result
+        }
+        Element::Paragraph(ref par) => {
+            let content = trim_spaces(inlines(&par.content));
+            if content.is_empty() {
+                vec![]
+            } else {
+                vec![json!({"t": "Para", "c": content})]
+            }
+        }
+        Element::List(ref list) => list_blocks(&list.content),
+        Element::Table(ref table) => vec![table_block(table)],
+        Element::Figure(ref figure) => vec![json!({"t": "Figure", "c": [
+            no_attr(),
+            [null, blocks(&figure.caption)],
+            blocks(&figure.content),
+        ]})],
+        Element::Gallery(ref gallery) => {
+            vec![json!({"t": "Para", "c": inlines(&gallery.content)})]
+        }
+        Element::Redirect(ref redirect) => {
+            let target = text_content_vec(&redirect.target);
+            let link =
+                json!({"t": "Link", "c": [no_attr(), [str_inline(&target)], [target, "wikilink"]]});
+            vec![json!({"t": "Para", "c": [str_inline("REDIRECT"), space(), link]})]
+        }
+        Element::HorizontalRule(_) => vec![json!({"t": "HorizontalRule"})],
+        Element::Code(ref code) => {
+            vec![json!({"t": "CodeBlock", "c": [code_attr(code), code.text]})]
+        }
+        Element::Formatted(ref fmt) => match fmt.markup {
+            MarkupType::Blockquote => vec![json!({"t": "BlockQuote", "c": blocks(&fmt.content)})],
+            MarkupType::Preformatted => {
+                vec![json!({"t": "CodeBlock", "c": [no_attr(), text_content_vec(&fmt.content)]})]
+            }
+            _ => vec![json!({"t": "Plain", "c": inline(elem)})],
+        },
+        _ => vec![],
+    }
+}
+
+/// Convert list items to lists. A new list starts wherever the kind of list changes,
+/// definition terms and definitions form one definition list.
+fn list_blocks(items: &[Element]) -> Vec<Value> {
+    let mut result = vec![];
+    let mut current: Vec<&ListItem> = vec![];
+    for item in items.iter().filter_map(|e| e.as_list_item()) {
+        let same_list = current
+            .last()
+            .map(|last| list_type(last.kind) == list_type(item.kind))
+            .unwrap_or(true);
+        if !same_list {
+            result.push(list_block(&current));
+            current.clear();
+        }
+        current.push(item);
+    }
+    if !current.is_empty() {
+        result.push(list_block(&current));
+    }
+    result
+}
+
+/// The pandoc list type of a list item kind.
+fn list_type(kind: ListItemKind) -> &'static str {
+    match kind {
+        ListItemKind::Unordered => "BulletList",
+        ListItemKind::Ordered => "OrderedList",
+        ListItemKind::Definition | ListItemKind::DefinitionTerm => "DefinitionList",
+    }
+}
+
+/// Convert list items of the same list type to a pandoc list.
+fn list_block(items: &[&ListItem]) -> Value {
+    let list_type = list_type(items[0].kind);
+    if list_type == "DefinitionList" {
+        let mut entries: Vec<(Vec<Value>, Vec<Value>)> = vec![];
+        for item in items {
+            match item.kind {
+                ListItemKind::DefinitionTerm => entries.push((inlines(&item.content), vec![])),
+                _ => {
+                    if entries.is_empty() {
+                        entries.push((vec![], vec![]));
+                    }
+                    let definitions = &mut entries.last_mut().expect("no entry!").1;
+                    definitions.push(json!(blocks(&item.content)));
+                }
+            }
+        }
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(term, definitions)| json!([term, definitions]))
+            .collect();
+        return json!({"t": "DefinitionList", "c": entries});
+    }
+    let content: Vec<_> = items
+        .iter()
+        .map(|item| json!(blocks(&item.content)))
+        .collect();
+    if list_type == "OrderedList" {
+        let attributes = json!([1, {"t": "Decimal"}, {"t": "Period"}]);
+        json!({"t": "OrderedList", "c": [attributes, content]})
+    } else {
+        json!({"t": "BulletList", "c": content})
+    }
+}
+
+/// Convert a table. A first row of only header cells becomes the table head.
+fn table_block(table: &Table) -> Value {
+    let rows: Vec<&TableRow> = table.rows.iter().filter_map(|e| e.as_table_row()).collect();
+    let columns = rows.iter().map(|row| row.cells.len()).max().unwrap_or(0);
+    let is_head = |row: &TableRow| {
+        !row.cells.is_empty()
+            && row
+                .cells
+                .iter()
+                .all(|c| c.as_table_cell().map(|c| c.header).unwrap_or(false))
+    };
+    let head_rows = match rows.first() {
+        Some(row) if is_head(row) => 1,
+        _ => 0,
+    };
+    let row_json = |row: &&TableRow| {
+        let cells: Vec<_> = row
+            .cells
+            .iter()
+            .filter_map(|e| e.as_table_cell())
+            .map(|cell| json!([no_attr(), {"t": "AlignDefault"}, 1, 1, blocks(&cell.content)]))
+            .collect();
+        json!([no_attr(), cells])
+    };
+    let head: Vec<_> = rows[..head_rows].iter().map(row_json).collect();
+    let body: Vec<_> = rows[head_rows..].iter().map(row_json).collect();
+    let column_spec = json!([{"t": "AlignDefault"}, {"t": "ColWidthDefault"}]);
+    json!({"t": "Table", "c": [
+        no_attr(),
+        [null, blocks(&table.caption)],
+        vec![column_spec; columns],
+        [no_attr(), head],
+        [[no_attr(), 0, [], body]],
+        [no_attr(), []],
+    ]})
+}
+
+/// Convert elements to pandoc inlines.
+fn inlines(elems: &[Element]) -> Vec<Value> {
+    elems.iter().flat_map(inline).collect()
+}
+
+/// Remove leading and trailing spaces, which pandoc does not allow in blocks.
+fn trim_spaces(mut content: Vec<Value>) -> Vec<Value> {
+    let is_space = |v: &Value| v["t"] == "Space" || v["t"] == "SoftBreak";
+    while content.last().map(is_space).unwrap_or(false) {
+        content.pop();
+    }
+    let leading = content.iter().take_while(|v| is_space(v)).count();
+    content.drain(..leading);
+    content
+}
+
+fn str_inline(text: &str) -> Value {
+    json!({"t": "Str", "c": text})
+}
+
+fn space() -> Value {
+    json!({"t": "Space"})
+}
+
+/// Split text into words and spaces, like pandoc does.
+fn text_inlines(text: &str) -> Vec<Value> {
+    let mut result = vec![];
+    let mut word = String::new();
+    let mut pending_space: Option<Value> = None;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !word.is_empty() {
+                result.push(str_inline(&word));
+                word.clear();
+            }
+            if c == '\n' {
+                pending_space = Some(json!({"t": "SoftBreak"}));
+            } else if pending_space.is_none() {
+                pending_space = Some(space());
+            }
+            continue;
+        }
+        if let Some(space) = pending_space.take() {
+            result.push(space);
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        result.push(str_inline(&word));
+    }
+    result.extend(pending_space);
+    result
+}
+
+/// Convert an inline element to pandoc inlines.
+fn inline(elem: &Element) -> Vec<Value> {
+    match *elem {
+        Element::Text(ref text) => text_inlines(&text.text),
+        Element::Paragraph(ref par) => inlines(&par.content),
+        Element::Formatted(ref fmt) => {
+            let content = inlines(&fmt.content);
+            let text = || text_content_vec(&fmt.content);
+            vec![match fmt.markup {
+                MarkupType::Bold => json!({"t": "Strong", "c": content}),
+                MarkupType::Italic => json!({"t": "Emph", "c": content}),
+                MarkupType::StrikeThrough => json!({"t": "Strikeout", "c": content}),
+                MarkupType::Underline => json!({"t": "Underline", "c": content}),
+                MarkupType::Math => json!({"t": "Math", "c": [{"t": "InlineMath"}, text()]}),
+                MarkupType::Code | MarkupType::Preformatted => {
+                    json!({"t": "Code", "c": [no_attr(), text()]})
+                }
+                MarkupType::NoWiki => return text_inlines(&text()),
+                MarkupType::Blockquote => {
+                    json!({"t": "Quoted", "c": [{"t": "DoubleQuote"}, content]})
+                }
+            }]
+        }
+        Element::InternalReference(ref iref) => {
+            let target = text_content_vec(&iref.target);
+            let target = target.trim();
+            let caption = inlines(&iref.caption);
+            match iref.namespace() {
+                Some("Category") if !target.starts_with(':') => vec![],
+                Some("File") => {
+                    vec![json!({"t": "Image", "c": [no_attr(), caption, [target, ""]]})]
+                }
+                _ => {
+                    let target = target.trim_start_matches(':');
+                    let caption = if caption.is_empty() {
+                        text_inlines(target)
+                    } else {
+                        caption
+                    };
+                    vec![json!({"t": "Link", "c": [no_attr(), caption, [target, "wikilink"]]})]
+                }
+            }
+        }
+        Element::ExternalReference(ref eref) => {
+            let caption = if eref.caption.is_empty() {
+                vec![str_inline(&eref.target)]
+            } else {
+                inlines(&eref.caption)
+            };
+            vec![json!({"t": "Link", "c": [no_attr(), caption, [eref.target, ""]]})]
+        }
+        Element::HtmlTag(ref tag) if tag.name.eq_ignore_ascii_case("br") => {
+            vec![json!({"t": "LineBreak"})]
+        }
+        Element::HtmlTag(ref tag) => inlines(&tag.content),
+        Element::Figure(ref figure) => {
+            // a figure in inline content keeps its caption as text after the figure.
+            let mut result = inlines(&figure.content);
+            let caption = trim_spaces(inlines(&figure.caption));
+            if !caption.is_empty() {
+                result.push(space());
+                result.extend(caption);
+            }
+            result
+        }
+        Element::Code(ref code) => vec![json!({"t": "Code", "c": [code_attr(code), code.text]})],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn heading() {
+        let doc = to_pandoc_json(&parse("== Some ''title'' ==\ntext\n").unwrap());
+        assert_eq!(doc["pandoc-api-version"], json!([1, 23, 1]));
+        assert_eq!(
+            doc["blocks"],
+            json!([
+                {"t": "Header", "c": [2, ["", [], []], [
+                    {"t": "Str", "c": "Some"},
+                    {"t": "Space"},
+                    {"t": "Emph", "c": [{"t": "Str", "c": "title"}]},
+                ]]},
+                {"t": "Para", "c": [{"t": "Str", "c": "text"}]},
+            ])
+        );
+    }
+
+    #[test]
+    fn bullet_list() {
+        let doc = to_pandoc_json(&parse("* one\n** nested\n* [[two]]\n").unwrap());
+        let plain = |text: &str| json!({"t": "Plain", "c": [{"t": "Str", "c": text}]});
+        let link = json!({"t": "Link", "c": [
+            ["", [], []],
+            [{"t": "Str", "c": "two"}],
+            ["two", "wikilink"],
+        ]});
+        assert_eq!(
+            doc["blocks"],
+            json!([{"t": "BulletList", "c": [
+                [plain("one"), {"t": "BulletList", "c": [[plain("nested")]]}],
+                [{"t": "Plain", "c": [link]}],
+            ]}])
+        );
+    }
+
+    #[test]
+    fn inline_figure_keeps_caption() {
+        use crate::optional_transformations::wrap_figures;
+
+        let root = parse("== [[File:x.png|thumb|An image]] ==\n").unwrap();
+        let root = wrap_figures(root, &Default::default()).unwrap();
+        let doc = to_pandoc_json(&root);
+        let header = &doc["blocks"][0]["c"][2];
+        let words: Vec<_> = header
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|v| v["t"] == "Str")
+            .map(|v| v["c"].as_str().unwrap())
+            .collect();
+        assert_eq!(words[words.len() - 2..], ["An", "image"]);
+    }
+}
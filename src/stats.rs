@@ -0,0 +1,213 @@
+//! Statistics about the document tree.
+
+use crate::ast::*;
+use crate::extract::{text_content_iter, text_content_vec};
+use crate::traversion::Traversion;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+/// Text statistics of a single section.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SectionStat {
+    /// Text of the heading caption.
+    pub title: String,
+    /// Number of whitespace-separated words.
+    pub word_count: usize,
+    /// Number of characters, not counting whitespace.
+    pub char_count: usize,
+    /// Position of the section heading.
+    pub span: Span,
+}
+
+/// Collects section statistics for every heading.
+struct SectionCollector<'e> {
+    path: Vec<&'e Element>,
+    sections: Vec<SectionStat>,
+}
+
+impl<'e> Traversion<'e, bool> for SectionCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(
+        &mut self,
+        root: &'e Element,
+        include_subsections: bool,
+        _: &mut dyn io::Write,
+    ) -> io::Result<bool> {
+        if let Element::Heading(ref heading) = *root {
+            let text = text_content_iter(
+                heading
+                    .content
+                    .iter()
+                    .filter(|e| include_subsections || !matches!(e, Element::Heading(_))),
+            );
+
+            self.sections.push(SectionStat {
+                title: text_content_vec(&heading.caption),
+                word_count: text.split_whitespace().count(),
+                char_count: text.chars().filter(|c| !c.is_whitespace()).count(),
+                span: heading.position.clone(),
+            });
+        }
+        Ok(true)
+    }
+}
+
+/// Compute word and character counts for every section (heading) of a document,
+/// in document order.
+///
+/// If `include_subsections` is set, the text of nested sections is counted
+/// for their parent sections as well.
+pub fn section_stats(root: &Element, include_subsections: bool) -> Vec<SectionStat> {
+    let mut collector = SectionCollector {
+        path: vec![],
+        sections: vec![],
+    };
+    collector
+        .run(root, include_subsections, &mut io::sink())
+        .expect("writing to a sink failed!");
+    collector.sections
+}
+
+/// Structural statistics of a document tree.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct TreeStats {
+    /// Number of elements per variant name, like `Paragraph`.
+    pub counts: BTreeMap<String, usize>,
+    /// Number of elements on the longest path from the root, including the root.
+    pub max_depth: usize,
+    /// Number of elements in the tree, including the root.
+    pub total_nodes: usize,
+}
+
+/// Human-readable summary, one value per line, with counts aligned.
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.counts.keys().map(|name| name.len()).max().unwrap_or(0);
+        writeln!(f, "total nodes: {}", self.total_nodes)?;
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        for (name, count) in &self.counts {
+            writeln!(f, "  {:width$} {:>6}", name, count, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the elements of a tree.
+struct TreeStatsCollector<'e> {
+    path: Vec<&'e Element>,
+    stats: TreeStats,
+}
+
+impl<'e> Traversion<'e, ()> for TreeStatsCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        *self
+            .stats
+            .counts
+            .entry(root.get_variant_name().to_string())
+            .or_insert(0) += 1;
+        self.stats.total_nodes += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.path.len());
+        Ok(true)
+    }
+}
+
+/// Count the elements of a tree by variant and compute its depth.
+pub fn tree_stats(root: &Element) -> TreeStats {
+    let mut collector = TreeStatsCollector {
+        path: vec![],
+        stats: TreeStats::default(),
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    collector.stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn two_sections() {
+        let input = "== First ==\nOne two ''three''.\n=== Nested ===\nfour\n== Second ==\nfive\n";
+        let root = parse(input).unwrap();
+
+        let stats = section_stats(&root, false);
+        let summary: Vec<_> = stats
+            .iter()
+            .map(|s| (s.title.as_str(), s.word_count, s.char_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("First", 3, 12), ("Nested", 1, 4), ("Second", 1, 4)]
+        );
+        assert_eq!(stats[0].span.start.line, 1);
+
+        let stats = section_stats(&root, true);
+        assert_eq!((stats[0].word_count, stats[0].char_count), (5, 22));
+    }
+
+    #[test]
+    fn element_counts() {
+        let input = "== Title ==\n* one\n* ''two''\n\ntext [[link]]\n";
+        let stats = tree_stats(&parse(input).unwrap());
+
+        let counts: Vec<_> = stats
+            .counts
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("Document", 1),
+                ("Formatted", 1),
+                ("Heading", 1),
+                ("InternalReference", 1),
+                ("List", 1),
+                ("ListItem", 2),
+                ("Paragraph", 1),
+                ("Text", 5),
+            ]
+        );
+        assert_eq!(stats.total_nodes, 13);
+        // document > heading > list > item > formatted > text
+        assert_eq!(stats.max_depth, 6);
+    }
+
+    #[test]
+    fn format_tree_stats() {
+        let stats = tree_stats(&parse("text [[link]]\n").unwrap());
+        assert_eq!(
+            stats.to_string(),
+            "total nodes: 5\n\
+             max depth: 4\n  \
+             Document               1\n  \
+             InternalReference      1\n  \
+             Paragraph              1\n  \
+             Text                   2\n"
+        );
+    }
+}
@@ -2,3 +2,6 @@
 pub mod generated {
     include!(concat!(env!("OUT_DIR"), "/tests_generated.rs"));
 }
+
+mod parser;
+mod serialization;
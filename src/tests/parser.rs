@@ -0,0 +1,408 @@
+use crate::{
+    known_extension_tags, outline, parse, parse_attributes, parse_inline, parse_lossy,
+    parse_stream, transformations::StructuralKey, validate, Element, GeneralSettings, MWError,
+    Parser,
+};
+use std::io;
+
+#[test]
+fn parser_reuses_input() {
+    let input = "== Heading ==\nsome ''text'' and<!-- note -->more\n* item\n";
+    let parser = Parser::new(input);
+
+    let first = parser.parse().expect("first parse failed!");
+    let settings = GeneralSettings {
+        transparent_comments: true,
+        ..GeneralSettings::default()
+    };
+    let second = parser
+        .parse_with_settings(&settings)
+        .expect("second parse failed!");
+    let third = parser
+        .parse_with_settings(&GeneralSettings::default())
+        .expect("third parse failed!");
+
+    assert_ne!(first, second);
+    assert_eq!(first, third);
+    assert_eq!(first, parse(input).unwrap());
+    assert_eq!(parser.input(), input);
+}
+
+#[test]
+fn parser_reports_errors() {
+    let parser = Parser::new("text\n[[unclosed");
+    assert!(parser.parse().is_err());
+    assert!(parser.parse().is_err());
+}
+
+#[test]
+fn error_context_around_column() {
+    let input = format!("{}tail [[unclosed|ä", "word ".repeat(40));
+    let message = match parse(&input) {
+        Err(e) => e.to_string(),
+        Ok(r) => panic!("expected a parse error, got {:?}", r),
+    };
+    let context = message.lines().nth(1).expect("no error context!");
+    assert!(context.contains("tail [[unclosed|ä"), "{}", context);
+    assert!(context.chars().count() < input.chars().count());
+}
+
+#[test]
+fn parser_friendly_errors() {
+    let parser = Parser::new("[[link|caption\n");
+    let expected = |settings: &GeneralSettings| match parser.parse_with_settings(settings) {
+        Err(MWError::ParseError(e)) => e.expected,
+        r => panic!("expected a parse error, got {:?}", r),
+    };
+    let mut settings = GeneralSettings::default();
+    assert!(expected(&settings).contains(&"]]".to_string()));
+
+    settings.friendly_errors = true;
+    let friendly = expected(&settings);
+    assert!(friendly.contains(&"closing brackets `]]`".to_string()));
+    assert!(!friendly.contains(&"]]".to_string()));
+    assert_eq!(friendly.iter().filter(|t| *t == "whitespace").count(), 1);
+}
+
+#[test]
+fn raw_parse_error() {
+    let parser = Parser::new("text\n[[link|caption\n");
+    let settings = GeneralSettings {
+        friendly_errors: true,
+        ..GeneralSettings::default()
+    };
+    let error = match parser.parse_with_settings(&settings) {
+        Err(MWError::ParseError(e)) => e,
+        r => panic!("expected a parse error, got {:?}", r),
+    };
+    let raw = error.raw.clone().expect("no raw error!");
+    assert_eq!(raw.offset, error.position.offset);
+    assert_eq!((raw.line, raw.column), (3, 1));
+    assert!(raw.expected.contains(&"]]"));
+    assert!(!error.expected.contains(&"]]".to_string()));
+
+    let yaml = serde_yaml::to_string(&error).unwrap();
+    assert!(!yaml.contains("raw"));
+}
+
+#[test]
+fn parser_raw_extension_settings() {
+    let parser = Parser::new("<graph>{{x}}</graph>");
+    let mut settings = GeneralSettings::default();
+    settings.raw_extension_tags.insert("graph".into());
+
+    let raw = parser.parse_with_settings(&settings).unwrap();
+    let parsed = parser.parse().unwrap();
+    assert_ne!(raw, parsed);
+
+    let first_inline = |root: &Element| match *root {
+        Element::Document(ref doc) => match doc.content[0] {
+            Element::Paragraph(ref par) => par.content[0].clone(),
+            _ => panic!("expected a paragraph!"),
+        },
+        _ => panic!("expected a document!"),
+    };
+    match first_inline(&raw) {
+        Element::ExtensionTag(ref tag) => assert_eq!(tag.body, "{{x}}"),
+        ref e => panic!("expected an extension tag, got {:?}", e),
+    }
+    assert_eq!(first_inline(&parsed).get_variant_name(), "HtmlTag");
+}
+
+#[test]
+fn known_extension_tags_include_raw_tags() {
+    // the default raw extension tags are listed in `EXTENSION_TAGS`.
+    let mut settings = GeneralSettings::default();
+    let mut defaults: Vec<_> = known_extension_tags()
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+    defaults.sort();
+    assert_eq!(settings.known_extension_tags(), defaults);
+
+    settings.raw_extension_tags.insert("graph2".into());
+    assert!(settings
+        .known_extension_tags()
+        .contains(&"graph2".to_string()));
+    assert!(settings.is_known_extension_tag("Graph2"));
+    assert!(!GeneralSettings::default().is_known_extension_tag("graph2"));
+}
+
+#[test]
+fn attribute_list() {
+    let attrs = parse_attributes(r#"class="wikitable sortable" style='color: red' border=1"#)
+        .expect("parsing attributes failed!");
+    let pairs: Vec<_> = attrs
+        .iter()
+        .map(|a| (a.key.as_str(), a.value.as_str()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("class", "wikitable sortable"),
+            ("style", "color: red"),
+            ("border", "1")
+        ]
+    );
+    assert_eq!(attrs[2].position.start.col, 47);
+
+    assert!(parse_attributes("").unwrap().is_empty());
+    assert!(parse_attributes("  ").unwrap().is_empty());
+    assert!(parse_attributes("class=").is_err());
+}
+
+#[test]
+fn custom_behavior_switch() {
+    let parser = Parser::new("__CUSTOMSWITCH__ text\n");
+    let mut settings = GeneralSettings::default();
+    settings.behavior_switches.insert("CUSTOMSWITCH".into());
+
+    let first_inline = |root: &Element| match *root {
+        Element::Document(ref doc) => match doc.content[0] {
+            Element::Paragraph(ref par) => par.content[0].clone(),
+            _ => panic!("expected a paragraph!"),
+        },
+        _ => panic!("expected a document!"),
+    };
+    match first_inline(&parser.parse_with_settings(&settings).unwrap()) {
+        Element::MagicWord(ref word) => assert_eq!(word.name, "CUSTOMSWITCH"),
+        ref e => panic!("expected a behavior switch, got {:?}", e),
+    }
+    assert_eq!(
+        first_inline(&parser.parse().unwrap()).get_variant_name(),
+        "Text"
+    );
+}
+
+#[test]
+fn inline_content() {
+    let content =
+        parse_inline("\'\'\'bold\'\'\' and [[link]]").expect("parsing inline content failed!");
+    let variants: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+    assert_eq!(variants, vec!["Formatted", "Text", "InternalReference"]);
+
+    // block syntax is plain text.
+    let content = parse_inline("== no heading ==\n* no list").unwrap();
+    let variants: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+    assert_eq!(variants, vec!["Text"]);
+}
+
+#[test]
+fn inline_category_sort_key() {
+    let content = parse_inline("[[Category:X|k]]").unwrap();
+    let category = content[0].as_internal_reference().unwrap();
+    assert_eq!(category.sort_key.as_deref(), Some("k"));
+    assert_eq!(category.caption, vec![]);
+    let document = parse("[[Category:X|k]]").unwrap();
+    assert_eq!(document.as_document().unwrap().content, content);
+}
+
+#[test]
+fn inline_interwiki_link() {
+    let content = parse_inline("see [[wikipedia:Foo|Foo]]").unwrap();
+    let link = content[1].as_internal_reference().unwrap();
+    assert_eq!(link.interwiki.as_deref(), Some("wikipedia"));
+    let document = parse("see [[wikipedia:Foo|Foo]]").unwrap();
+    let paragraph = document.as_document().unwrap().content[0].as_paragraph();
+    assert_eq!(paragraph.unwrap().content, content);
+}
+
+#[test]
+fn inline_file_link() {
+    let content = parse_inline("[[File:x.png|thumb|link=Page|Caption]]").unwrap();
+    let file = content[0].as_internal_reference().unwrap();
+    assert_eq!(file.link.as_deref(), Some("Page"));
+    assert_eq!(file.options.len(), 1);
+    let document = parse("[[File:x.png|thumb|link=Page|Caption]]").unwrap();
+    assert_eq!(document.as_document().unwrap().content, content);
+}
+
+#[test]
+fn parse_stream_segments() {
+    let input = "first ''article''\n----ARTICLE----\n\n== Second ==\ntext\n----ARTICLE----\n";
+    let docs: Vec<_> = parse_stream(io::Cursor::new(input), "----ARTICLE----")
+        .map(|doc| doc.expect("parsing a segment failed!"))
+        .collect();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0], parse("first ''article''\n").unwrap());
+    let second = docs[1].as_document().unwrap();
+    assert_eq!(second.content[0].get_variant_name(), "Heading");
+}
+
+#[test]
+fn transparent_comments() {
+    let parser = Parser::new("a<!--x-->b");
+    let paragraph = |settings: &GeneralSettings| {
+        let root = parser.parse_with_settings(settings).unwrap();
+        let par = root.as_document().unwrap().content[0].clone();
+        par.as_paragraph().unwrap().content.clone()
+    };
+    fn variants(content: &[Element]) -> Vec<&str> {
+        content.iter().map(|e| e.get_variant_name()).collect()
+    }
+
+    let separated = paragraph(&GeneralSettings::default());
+    assert_eq!(variants(&separated), vec!["Text", "Comment", "Text"]);
+
+    let settings = GeneralSettings {
+        transparent_comments: true,
+        ..GeneralSettings::default()
+    };
+    let merged = paragraph(&settings);
+    assert_eq!(variants(&merged), vec!["Text", "Comment"]);
+    let text = merged[0].as_text().unwrap();
+    assert_eq!(text.text, "ab");
+    assert_eq!(
+        (text.position.start.offset, text.position.end.offset),
+        (0, 10)
+    );
+}
+
+#[test]
+fn document_outline() {
+    let input = "intro\n== First ==\ntext\n=== ''Sub'' section ===\n* item\n== Second ==\n";
+    let headings = outline(input).unwrap();
+    let summary: Vec<_> = headings
+        .iter()
+        .map(|(depth, caption, span)| {
+            (
+                *depth,
+                caption.as_str(),
+                &input[span.start.offset..span.start.offset + 3],
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (2, "First", "== "),
+            (3, "Sub section", "==="),
+            (2, "Second", "== ")
+        ]
+    );
+
+    let root = parse(input).unwrap();
+    let first = &root.as_document().unwrap().content[1];
+    assert_eq!(first.get_position(), &headings[0].2);
+}
+
+#[test]
+fn unknown_tags_as_errors() {
+    let parser = Parser::new("<frobnicate a=1>x</frobnicate> <span>y</span>");
+    let tags = |settings: &GeneralSettings| -> Vec<String> {
+        let root = parser.parse_with_settings(settings).unwrap();
+        let par = root.as_document().unwrap().content[0].clone();
+        let content = &par.as_paragraph().unwrap().content;
+        vec![
+            content[0].get_variant_name().to_string(),
+            content.last().unwrap().get_variant_name().to_string(),
+        ]
+    };
+    assert_eq!(
+        tags(&GeneralSettings::default()),
+        vec!["HtmlTag", "HtmlTag"]
+    );
+
+    let settings = GeneralSettings {
+        unknown_tags_as_errors: true,
+        ..GeneralSettings::default()
+    };
+    assert_eq!(tags(&settings), vec!["Error", "HtmlTag"]);
+    let root = parser.parse_with_settings(&settings).unwrap();
+    let par = &root.as_document().unwrap().content[0];
+    let content = &par.as_paragraph().unwrap().content;
+    let error = content[0].as_error().unwrap();
+    assert_eq!(error.message, "unknown html tag \"frobnicate\".");
+    assert_eq!(error.position.end.offset, 16);
+    assert_eq!(content[1].as_text().unwrap().text, "x");
+}
+
+#[test]
+fn unknown_tags_keep_content() {
+    let settings = GeneralSettings {
+        unknown_tags_as_errors: true,
+        ..GeneralSettings::default()
+    };
+    let root = Parser::new("<foo>a <bar>''b''</bar> <span>c</span></foo><baz/>")
+        .parse_with_settings(&settings)
+        .unwrap();
+    let par = &root.as_document().unwrap().content[0];
+    let content = &par.as_paragraph().unwrap().content;
+    let variants: Vec<_> = content.iter().map(|e| e.get_variant_name()).collect();
+    assert_eq!(
+        variants,
+        vec![
+            "Error",
+            "Text",
+            "Error",
+            "Formatted",
+            "Text",
+            "HtmlTag",
+            "Error"
+        ]
+    );
+    assert_eq!(content[3].as_formatted().unwrap().content.len(), 1);
+    assert_eq!(content[6].get_position().end.offset, 50);
+    assert_eq!(validate::validate(&root), vec![]);
+}
+
+#[test]
+fn lossy_parse_of_broken_markup() {
+    let input = "intro\n\n== A ==\ntext [[broken\n\nmore ''text''\n== B ==\n* item\n";
+    assert!(parse(input).is_err());
+
+    let root = parse_lossy(input);
+    let doc = root.as_document().expect("not a document!");
+    assert_eq!(doc.position.end.offset, input.len());
+    let variants: Vec<_> = doc.content.iter().map(|e| e.get_variant_name()).collect();
+    assert_eq!(variants, vec!["Paragraph", "Error", "Paragraph", "Heading"]);
+
+    let section = doc.content[3].as_heading().unwrap();
+    assert_eq!(section.content[0].get_variant_name(), "List");
+    let error = doc.content[1].get_position();
+    assert_eq!(
+        &input[error.start.offset..error.end.offset],
+        "== A ==\ntext [[broken\n"
+    );
+
+    let valid = "== A ==\ntext\n";
+    assert_eq!(parse_lossy(valid), parse(valid).unwrap());
+}
+
+#[test]
+fn lossy_parse_keeps_structure_of_valid_parts() {
+    let valid =
+        "== B ==\n* item\n:def\n\nmore ''text''\n=== C ===\n{|\n| cell\n|}\n[[x]] {{t|a}}\n";
+    let input = format!("intro\n\n== A ==\ntext [[broken\n\n{}", valid);
+    assert!(parse(&input).is_err());
+
+    let lossy = parse_lossy(&input);
+    let section = lossy.as_document().unwrap().content.last().unwrap().clone();
+    let expected = parse(valid).unwrap().as_document().unwrap().content[0].clone();
+    assert_eq!(StructuralKey::new(section), StructuralKey::new(expected));
+}
+
+#[test]
+fn table_attribute_templates() {
+    let input = "{| {{table style}}\n| {{cell style|x=1}} | text {{inline}}\n|}\n";
+    let root = parse(input).unwrap();
+    let names: Vec<_> = crate::extract::template_invocations(&root)
+        .into_iter()
+        .map(|t| t.name.unwrap_or_default())
+        .collect();
+    assert_eq!(names, vec!["table style", "cell style", "inline"]);
+
+    let row = &root.as_document().unwrap().content[0]
+        .as_table()
+        .unwrap()
+        .rows[0];
+    let cell = row.as_table_row().unwrap().cells[0]
+        .as_table_cell()
+        .unwrap();
+    assert_eq!(cell.attributes[0].value, "{{cell style|x=1}}");
+    let span = &cell.attributes[0].position;
+    assert_eq!(
+        &input[span.start.offset..span.end.offset],
+        "{{cell style|x=1}}"
+    );
+}
@@ -0,0 +1,96 @@
+use crate::{
+    parse, to_json, to_json_truncated, to_json_writer, to_yaml, to_yaml_truncated, to_yaml_writer,
+    Element,
+};
+
+#[test]
+fn subtree_roundtrip() {
+    let root = parse("== Heading ==\ncontent ''text''\n").unwrap();
+    let heading = match root {
+        Element::Document(ref doc) => &doc.content[0],
+        _ => panic!("expected a document!"),
+    };
+    assert_eq!(heading.get_variant_name(), "Heading");
+
+    let from_yaml: Element = serde_yaml::from_str(&to_yaml(heading)).unwrap();
+    let from_json: Element = serde_json::from_str(&to_json(heading)).unwrap();
+    assert_eq!(&from_yaml, heading);
+    assert_eq!(&from_json, heading);
+}
+
+#[test]
+fn heading_without_marker_counts() {
+    let old = "{\"type\": \"heading\", \"depth\": 2, \"caption\": [], \"content\": []}";
+    let heading: Element = serde_json::from_str(old).unwrap();
+    let heading = heading.as_heading().unwrap();
+    assert_eq!(heading.open_markers, None);
+    assert_eq!(heading.close_markers, None);
+    let parsed = parse("==  ==\n").unwrap();
+    let parsed = parsed.as_document().unwrap().content[0]
+        .as_heading()
+        .unwrap();
+    assert_eq!(parsed.open_markers, Some(2));
+    assert_ne!(heading, parsed);
+}
+
+#[test]
+fn serialize_to_writer() {
+    let root = parse("text with [[link]]\n* item\n").unwrap();
+
+    let mut yaml = vec![];
+    to_yaml_writer(&root, &mut yaml).unwrap();
+    assert_eq!(yaml, to_yaml(&root).into_bytes());
+
+    let mut json = vec![];
+    to_json_writer(&root, &mut json).unwrap();
+    assert_eq!(json, to_json(&root).into_bytes());
+}
+
+#[test]
+fn serialize_with_depth_cap() {
+    let input = format!("{}deep{}\n", "{{t|".repeat(6), "}}".repeat(6));
+    let root = parse(&input).unwrap();
+    let argument = |root: &Element| {
+        root.as_document().unwrap().content[0]
+            .as_template()
+            .unwrap()
+            .content[0]
+            .clone()
+    };
+
+    let json = to_json_truncated(&root, 1);
+    let capped: Element = serde_json::from_str(&json).unwrap();
+    let placeholder = argument(&capped);
+    let placeholder = placeholder.as_error().expect("no placeholder!");
+    assert_eq!(
+        placeholder.message,
+        "TemplateArgument truncated at maximum depth."
+    );
+    assert_eq!(&placeholder.position, argument(&root).get_position());
+    assert!(!json.contains("deep"));
+    assert!(to_json(&root).contains("deep"));
+
+    let from_yaml: Element = serde_yaml::from_str(&to_yaml_truncated(&root, 1)).unwrap();
+    assert_eq!(from_yaml, capped);
+    assert_eq!(to_json_truncated(&root, 100), to_json(&root));
+}
+
+#[cfg(feature = "simple_text")]
+#[test]
+fn simple_text_roundtrip() {
+    let root = parse("plain ''italic'' [[link|caption]]\n").unwrap();
+    let json = to_json(&root);
+    assert!(json.contains(r#"["plain ","#), "{}", json);
+    assert!(!json.contains(r#""type":"text""#), "{}", json);
+
+    let from_json: Element = serde_json::from_str(&json).unwrap();
+    let from_yaml: Element = serde_yaml::from_str(&to_yaml(&root)).unwrap();
+    assert_eq!(from_json, root);
+    assert_eq!(from_yaml, root);
+
+    let tagged = r#"{"type": "paragraph", "content": [{"type": "text", "text": "x"}]}"#;
+    let from_tagged: Element = serde_json::from_str(tagged).unwrap();
+    let from_simple: Element =
+        serde_json::from_str(r#"{"type": "paragraph", "content": ["x"]}"#).unwrap();
+    assert_eq!(from_tagged, from_simple);
+}
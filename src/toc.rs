@@ -0,0 +1,145 @@
+//! Table of contents of a document, following MediaWiki's rules for its visibility.
+
+use crate::ast::*;
+use crate::extract::text_content_vec;
+use crate::traversion::Traversion;
+use serde_derive::{Deserialize, Serialize};
+use std::io;
+
+/// Minimum number of headings for a table of contents without `__FORCETOC__` or `__TOC__`.
+pub const TOC_MIN_HEADINGS: usize = 4;
+
+/// A section listed in the table of contents.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct TocEntry {
+    /// Depth of the heading, e.g. 2 for `== Title ==`.
+    pub depth: usize,
+    /// Nesting level in the table of contents, starting with 1.
+    pub level: usize,
+    /// Section number, e.g. `2.1`.
+    pub number: String,
+    /// Text of the heading caption.
+    pub title: String,
+    /// Position of the heading.
+    pub span: Span,
+}
+
+/// Collects headings and behavior switches.
+struct TocCollector<'e> {
+    path: Vec<&'e Element>,
+    counters: Vec<usize>,
+    entries: Vec<TocEntry>,
+    switches: Vec<String>,
+}
+
+impl<'e> Traversion<'e, ()> for TocCollector<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        match *root {
+            Element::Heading(ref heading) => {
+                let level = self
+                    .path
+                    .iter()
+                    .filter(|e| matches!(e, Element::Heading(_)))
+                    .count();
+                self.counters.resize(level, 0);
+                self.counters[level - 1] += 1;
+                let number: Vec<String> = self.counters.iter().map(|c| c.to_string()).collect();
+                self.entries.push(TocEntry {
+                    depth: heading.depth,
+                    level,
+                    number: number.join("."),
+                    title: text_content_vec(&heading.caption).trim().to_string(),
+                    span: heading.position.clone(),
+                });
+            }
+            Element::MagicWord(ref word) => self.switches.push(word.name.clone()),
+            _ => (),
+        };
+        Ok(true)
+    }
+}
+
+/// Build the table of contents of a document, or `None` if MediaWiki would not show one.
+///
+/// A table of contents is shown for at least `TOC_MIN_HEADINGS` headings.
+/// `__NOTOC__` hides it, while `__FORCETOC__` and `__TOC__` show it regardless
+/// of the number of headings, even together with `__NOTOC__`.
+pub fn build_toc(root: &Element) -> Option<Vec<TocEntry>> {
+    let mut collector = TocCollector {
+        path: vec![],
+        counters: vec![],
+        entries: vec![],
+        switches: vec![],
+    };
+    collector
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+
+    let has = |name: &str| collector.switches.iter().any(|s| s == name);
+    let forced = has("TOC") || has("FORCETOC");
+    let enough = collector.entries.len() >= TOC_MIN_HEADINGS;
+    if forced || (enough && !has("NOTOC")) {
+        Some(collector.entries)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const SECTIONS: &str = "== A ==\n=== A1 ===\n=== A2 ===\n== B ==\n";
+
+    #[test]
+    fn toc_numbering() {
+        let toc = build_toc(&parse(SECTIONS).unwrap()).expect("no toc!");
+        let entries: Vec<_> = toc
+            .iter()
+            .map(|e| (e.number.as_str(), e.title.as_str(), e.depth))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("1", "A", 2),
+                ("1.1", "A1", 3),
+                ("1.2", "A2", 3),
+                ("2", "B", 2)
+            ]
+        );
+        assert_eq!(build_toc(&parse("== A ==\n== B ==\n").unwrap()), None);
+    }
+
+    #[test]
+    fn toc_notoc() {
+        let input = format!("__NOTOC__\n{}", SECTIONS);
+        assert_eq!(build_toc(&parse(&input).unwrap()), None);
+
+        let forced = format!("__NOTOC__ __FORCETOC__\n{}", SECTIONS);
+        assert!(build_toc(&parse(&forced).unwrap()).is_some());
+    }
+
+    #[test]
+    fn toc_position_switch() {
+        let input = "__TOC__\n== Only ==\n";
+        let toc = build_toc(&parse(input).unwrap()).expect("no toc!");
+        assert_eq!(toc.len(), 1);
+
+        let with_notoc = format!("__NOTOC__\n__TOC__\n{}", SECTIONS);
+        assert_eq!(
+            build_toc(&parse(&with_notoc).unwrap()).map(|t| t.len()),
+            Some(4)
+        );
+    }
+}
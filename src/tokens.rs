@@ -0,0 +1,137 @@
+//! Lexical tokens of the input, e.g. for syntax highlighting in editors.
+//! Tokenizing never fails, any character which is not markup is text.
+
+use crate::ast::Span;
+use crate::default_transformations::GeneralSettings;
+use crate::grammar;
+use crate::util;
+use serde_derive::{Deserialize, Serialize};
+
+/// Kinds of tokens. Everything which is not markup is `Text`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Text,
+    Bold,
+    Italic,
+    BoldItalic,
+    /// `[[`
+    LinkOpen,
+    /// `]]`
+    LinkClose,
+    /// `[`
+    ExternalLinkOpen,
+    /// `]`
+    ExternalLinkClose,
+    /// `{{`
+    TemplateOpen,
+    /// `}}`
+    TemplateClose,
+    /// `|` or `{{!}}`
+    Pipe,
+    TableStart,
+    TableEnd,
+    TableRowSeparator,
+    TableCaption,
+    /// `||`, `!!` or `!` at the start of a line.
+    CellSeparator,
+    HeadingMarker,
+    ListMarker,
+    HorizontalRule,
+    /// A behavior switch like `__NOTOC__`.
+    MagicWord,
+    CommentStart,
+    CommentEnd,
+    /// An opening or self-closing html tag, including attributes.
+    TagOpen,
+    TagClose,
+}
+
+/// A token of the input with its position.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Split the input into markup and text tokens. Consecutive text is one token.
+///
+/// Tokens are recognized without context, e.g. `[` is always an external link start,
+/// and the content of comments or `<nowiki>` is tokenized as well.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let source_lines = util::get_source_lines(input);
+    let settings = GeneralSettings::default();
+    let raw = grammar::tokens(input, &source_lines, &settings, &Default::default())
+        .expect("tokenizing cannot fail, any character is text!");
+
+    let mut merged: Vec<(TokenKind, usize, usize)> = vec![];
+    for token in raw {
+        match merged.last_mut() {
+            Some(last) if last.0 == TokenKind::Text && token.0 == TokenKind::Text => {
+                last.2 = token.2
+            }
+            _ => merged.push(token),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(kind, start, end)| Token {
+            kind,
+            span: Span::new(start, end, &source_lines),
+        })
+        .collect()
+}
+
+/// Get the source text of a token.
+pub fn token_text<'a>(input: &'a str, token: &Token) -> &'a str {
+    &input[token.span.as_range()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bold_and_link() {
+        let input = "'''a''' [[b]]";
+        let tokens: Vec<_> = tokenize(input)
+            .iter()
+            .map(|t| (t.kind, token_text(input, t)))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Bold, "'''"),
+                (TokenKind::Text, "a"),
+                (TokenKind::Bold, "'''"),
+                (TokenKind::Text, " "),
+                (TokenKind::LinkOpen, "[["),
+                (TokenKind::Text, "b"),
+                (TokenKind::LinkClose, "]]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_start_tokens() {
+        let input = "== a = b ==\n* x == y\n{|\n! h || c\n|}";
+        let kinds: Vec<_> = tokenize(input)
+            .iter()
+            .filter(|t| t.kind != TokenKind::Text)
+            .map(|t| (t.kind, t.span.start.line))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::HeadingMarker, 1),
+                (TokenKind::HeadingMarker, 1),
+                (TokenKind::ListMarker, 2),
+                (TokenKind::TableStart, 3),
+                (TokenKind::CellSeparator, 4),
+                (TokenKind::CellSeparator, 4),
+                (TokenKind::TableEnd, 5),
+            ]
+        );
+    }
+}
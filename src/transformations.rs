@@ -10,10 +10,17 @@ pub type TResult = Result<Element, TransformationError>;
 pub type TListResult = Result<Vec<Element>, TransformationError>;
 
 /// Signature of an in-place transformation function
-pub type TFuncInplace<S> = Fn(Element, S) -> TResult;
+pub type TFuncInplace<S> = dyn Fn(Element, S) -> TResult;
 
 /// Signature of a cloning transformation function
-pub type TFunc<S> = Fn(&Element, &[&Element], S) -> TResult;
+pub type TFunc<S> = dyn Fn(&Element, &[&Element], S) -> TResult;
+
+/// Signature of a function applying an in-place transformation to a list of children.
+pub type TContentFuncInplace<'f, S> =
+    dyn Fn(&TFuncInplace<S>, &mut Vec<Element>, S) -> TListResult + 'f;
+
+/// Signature of a function applying a cloning transformation to a list of children.
+pub type TContentFunc<'f, S> = dyn Fn(&TFunc<S>, &[Element], &[&Element], S) -> TListResult + 'f;
 
 /// Apply a given transformation function to a list of elements, without mutating the original.
 pub fn apply_func_clone<S: Copy>(
@@ -42,6 +49,51 @@ pub fn apply_func_drain<S: Copy>(
     Ok(result)
 }
 
+/// Move the elements matching `is_block` out of the paragraphs of a list of elements,
+/// e.g. after a transformation produced block elements from inline ones.
+/// A paragraph is split around such elements, empty parts are removed.
+pub fn lift_from_paragraphs(
+    content: Vec<Element>,
+    is_block: &dyn Fn(&Element) -> bool,
+) -> Vec<Element> {
+    fn flush(result: &mut Vec<Element>, part: &mut Vec<Element>) {
+        let is_empty = part.iter().all(|e| match *e {
+            Element::Text(ref t) => t.text.trim().is_empty(),
+            _ => false,
+        });
+        if !is_empty {
+            result.push(Element::Paragraph(Paragraph {
+                position: Span::enclosing(part),
+                id: None,
+                content: std::mem::take(part),
+            }));
+        }
+        part.clear();
+    }
+
+    let mut result = vec![];
+    for child in content {
+        let par = match child {
+            Element::Paragraph(par) if par.content.iter().any(is_block) => par,
+            _ => {
+                result.push(child);
+                continue;
+            }
+        };
+        let mut part = vec![];
+        for elem in par.content {
+            if is_block(&elem) {
+                flush(&mut result, &mut part);
+                result.push(elem);
+            } else {
+                part.push(elem);
+            }
+        }
+        flush(&mut result, &mut part);
+    }
+    result
+}
+
 /// Recursively apply a transformation function `func` to all children of element `root`.
 pub fn recurse_inplace<S: Copy>(func: &TFuncInplace<S>, root: Element, settings: S) -> TResult {
     recurse_inplace_template(func, root, settings, &apply_func_drain)
@@ -52,7 +104,7 @@ pub fn recurse_inplace_template<S: Copy>(
     func: &TFuncInplace<S>,
     mut root: Element,
     settings: S,
-    content_func: &Fn(&TFuncInplace<S>, &mut Vec<Element>, S) -> TListResult,
+    content_func: &TContentFuncInplace<'_, S>,
 ) -> TResult {
     match root {
         Element::Document(ref mut e) => {
@@ -76,6 +128,7 @@ pub fn recurse_inplace_template<S: Copy>(
             e.content.append(&mut temp);
         }
         Element::TableCell(ref mut e) => {
+            attribute_values_inplace(func, &mut e.attributes, settings, content_func)?;
             let mut temp = content_func(func, &mut e.content, settings)?;
             e.content.append(&mut temp);
         }
@@ -87,6 +140,16 @@ pub fn recurse_inplace_template<S: Copy>(
             let mut temp = content_func(func, &mut e.content, settings)?;
             e.content.append(&mut temp);
         }
+        Element::Figure(ref mut e) => {
+            let mut content = content_func(func, &mut e.content, settings)?;
+            let mut caption = content_func(func, &mut e.caption, settings)?;
+            e.content.append(&mut content);
+            e.caption.append(&mut caption);
+        }
+        Element::Redirect(ref mut e) => {
+            let mut temp = content_func(func, &mut e.target, settings)?;
+            e.target.append(&mut temp);
+        }
         Element::Heading(ref mut e) => {
             let mut content = content_func(func, &mut e.content, settings)?;
             let mut caption = content_func(func, &mut e.caption, settings)?;
@@ -103,6 +166,14 @@ pub fn recurse_inplace_template<S: Copy>(
             let mut value = content_func(func, &mut e.value, settings)?;
             e.value.append(&mut value);
         }
+        Element::Parameter(ref mut e) => {
+            let mut name = content_func(func, &mut e.name, settings)?;
+            e.name.append(&mut name);
+            if let Some(ref mut default) = e.default {
+                let mut value = content_func(func, default, settings)?;
+                default.append(&mut value);
+            }
+        }
         Element::InternalReference(ref mut e) => {
             let mut target = content_func(func, &mut e.target, settings)?;
             let mut caption = content_func(func, &mut e.caption, settings)?;
@@ -121,20 +192,45 @@ pub fn recurse_inplace_template<S: Copy>(
             e.caption.append(&mut caption);
         }
         Element::Table(ref mut e) => {
+            attribute_values_inplace(func, &mut e.attributes, settings, content_func)?;
+            attribute_values_inplace(func, &mut e.caption_attributes, settings, content_func)?;
             let mut caption = content_func(func, &mut e.caption, settings)?;
             let mut rows = content_func(func, &mut e.rows, settings)?;
             e.caption.append(&mut caption);
             e.rows.append(&mut rows);
         }
         Element::TableRow(ref mut e) => {
+            attribute_values_inplace(func, &mut e.attributes, settings, content_func)?;
             let mut cells = content_func(func, &mut e.cells, settings)?;
             e.cells.append(&mut cells);
         }
-        Element::Text(_) | Element::Comment(_) | Element::Error(_) => (),
+        Element::Text(_)
+        | Element::Comment(_)
+        | Element::ExtensionTag(_)
+        | Element::MagicWord(_)
+        | Element::Variable(_)
+        | Element::Code(_)
+        | Element::HorizontalRule(_)
+        | Element::Error(_) => (),
     };
     Ok(root)
 }
 
+/// Apply `content_func` to the elements of attribute values, like templates providing
+/// table attributes.
+fn attribute_values_inplace<S: Copy>(
+    func: &TFuncInplace<S>,
+    attributes: &mut [TagAttribute],
+    settings: S,
+    content_func: &TContentFuncInplace<'_, S>,
+) -> Result<(), TransformationError> {
+    for attr in attributes {
+        let mut value = content_func(func, &mut attr.value_elements, settings)?;
+        attr.value_elements.append(&mut value);
+    }
+    Ok(())
+}
+
 /// Recursively apply a transformation function `func` to all children of element `root`, cloning the input.
 pub fn recurse_clone<S: Copy>(
     func: &TFunc<S>,
@@ -151,101 +247,377 @@ pub fn recurse_clone_template<S: Copy>(
     root: &Element,
     path: &[&Element],
     settings: S,
-    content_func: &Fn(&TFunc<S>, &[Element], &[&Element], S) -> TListResult,
+    content_func: &TContentFunc<'_, S>,
 ) -> TResult {
     let mut path = path.to_owned();
     path.push(root);
     let new = match *root {
         Element::Document(ref e) => Element::Document(Document {
             position: e.position.clone(),
+            id: e.id,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Heading(ref e) => Element::Heading(Heading {
+            open_markers: e.open_markers,
+            close_markers: e.close_markers,
             position: e.position.clone(),
+            id: e.id,
             depth: e.depth,
             caption: content_func(func, &e.caption, &path, settings)?,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Formatted(ref e) => Element::Formatted(Formatted {
             position: e.position.clone(),
+            id: e.id,
             markup: e.markup,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Paragraph(ref e) => Element::Paragraph(Paragraph {
             position: e.position.clone(),
+            id: e.id,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Template(ref e) => Element::Template(Template {
             position: e.position.clone(),
+            id: e.id,
             name: content_func(func, &e.name, &path, settings)?,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::TemplateArgument(ref e) => Element::TemplateArgument(TemplateArgument {
             position: e.position.clone(),
+            id: e.id,
             name: e.name.clone(),
             value: content_func(func, &e.value, &path, settings)?,
         }),
+        Element::Parameter(ref e) => Element::Parameter(Parameter {
+            position: e.position.clone(),
+            id: e.id,
+            name: content_func(func, &e.name, &path, settings)?,
+            default: match e.default {
+                Some(ref default) => Some(content_func(func, default, &path, settings)?),
+                None => None,
+            },
+        }),
         Element::InternalReference(ref e) => {
             let mut new_options = vec![];
             for option in &e.options {
-                new_options.push(content_func(func, &option, &path, settings)?);
+                new_options.push(content_func(func, option, &path, settings)?);
             }
 
             Element::InternalReference(InternalReference {
                 position: e.position.clone(),
+                id: e.id,
                 target: content_func(func, &e.target, &path, settings)?,
                 options: new_options,
                 caption: content_func(func, &e.caption, &path, settings)?,
+                sort_key: e.sort_key.clone(),
+                link: e.link.clone(),
+                interwiki: e.interwiki.clone(),
             })
         }
         Element::ExternalReference(ref e) => Element::ExternalReference(ExternalReference {
             position: e.position.clone(),
+            id: e.id,
             target: e.target.clone(),
             caption: content_func(func, &e.caption, &path, settings)?,
         }),
         Element::ListItem(ref e) => Element::ListItem(ListItem {
             position: e.position.clone(),
+            id: e.id,
             depth: e.depth,
             kind: e.kind,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::List(ref e) => Element::List(List {
             position: e.position.clone(),
+            id: e.id,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Table(ref e) => Element::Table(Table {
             position: e.position.clone(),
-            attributes: e.attributes.clone(),
+            id: e.id,
+            attributes: attribute_values_clone(func, &e.attributes, &path, settings, content_func)?,
             caption: content_func(func, &e.caption, &path, settings)?,
-            caption_attributes: e.caption_attributes.clone(),
+            caption_attributes: attribute_values_clone(
+                func,
+                &e.caption_attributes,
+                &path,
+                settings,
+                content_func,
+            )?,
             rows: content_func(func, &e.rows, &path, settings)?,
         }),
         Element::TableRow(ref e) => Element::TableRow(TableRow {
             position: e.position.clone(),
-            attributes: e.attributes.clone(),
+            id: e.id,
+            attributes: attribute_values_clone(func, &e.attributes, &path, settings, content_func)?,
             cells: content_func(func, &e.cells, &path, settings)?,
         }),
         Element::TableCell(ref e) => Element::TableCell(TableCell {
             position: e.position.clone(),
+            id: e.id,
             header: e.header,
-            attributes: e.attributes.clone(),
+            attributes: attribute_values_clone(func, &e.attributes, &path, settings, content_func)?,
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Comment(ref e) => Element::Comment(e.clone()),
         Element::Text(ref e) => Element::Text(e.clone()),
         Element::Error(ref e) => Element::Error(e.clone()),
+        Element::ExtensionTag(ref e) => Element::ExtensionTag(e.clone()),
+        Element::MagicWord(ref e) => Element::MagicWord(e.clone()),
+        Element::Variable(ref e) => Element::Variable(e.clone()),
+        Element::Code(ref e) => Element::Code(e.clone()),
+        Element::HorizontalRule(ref e) => Element::HorizontalRule(e.clone()),
         Element::HtmlTag(ref e) => Element::HtmlTag(HtmlTag {
             position: e.position.clone(),
+            id: e.id,
             name: e.name.clone(),
             attributes: e.attributes.clone(),
             content: content_func(func, &e.content, &path, settings)?,
         }),
         Element::Gallery(ref e) => Element::Gallery(Gallery {
             position: e.position.clone(),
+            id: e.id,
             attributes: e.attributes.clone(),
             content: content_func(func, &e.content, &path, settings)?,
         }),
+        Element::Figure(ref e) => Element::Figure(Figure {
+            position: e.position.clone(),
+            id: e.id,
+            content: content_func(func, &e.content, &path, settings)?,
+            caption: content_func(func, &e.caption, &path, settings)?,
+        }),
+        Element::Redirect(ref e) => Element::Redirect(Redirect {
+            position: e.position.clone(),
+            id: e.id,
+            target: content_func(func, &e.target, &path, settings)?,
+        }),
     };
     path.pop();
     Ok(new)
 }
+
+/// Apply `content_func` to the elements of attribute values, cloning the attributes.
+fn attribute_values_clone<S: Copy>(
+    func: &TFunc<S>,
+    attributes: &[TagAttribute],
+    path: &[&Element],
+    settings: S,
+    content_func: &TContentFunc<'_, S>,
+) -> Result<Vec<TagAttribute>, TransformationError> {
+    let mut result = vec![];
+    for attr in attributes {
+        result.push(TagAttribute {
+            value_elements: content_func(func, &attr.value_elements, path, settings)?,
+            ..attr.clone()
+        });
+    }
+    Ok(result)
+}
+
+/// Grow the span of every element with children to contain the spans of its children,
+/// bottom-up, e.g. after content was moved between elements.
+/// Spans never shrink, so markup around the children, like `''` of italic text,
+/// stays part of the span. Spans of elements without children are kept.
+pub fn recompute_spans(root: Element) -> Element {
+    fn recompute(root: Element, _: ()) -> TResult {
+        let mut root = recurse_inplace(&recompute, root, ())?;
+        let span = children(&root)
+            .iter()
+            .fold(root.get_position().clone(), |span, child| {
+                span.merge(child.get_position())
+            });
+        *root.get_position_mut() = span;
+        Ok(root)
+    }
+    recompute(root, ()).expect("recomputing spans failed!")
+}
+
+/// Replace all elements nested deeper than `max_depth` below the root by an `Error`
+/// placeholder with the position of the removed subtree, e.g. to serialize a preview
+/// of a very deep tree. The root has depth 0.
+pub fn truncate_depth(root: Element, max_depth: usize) -> Element {
+    fn truncate(root: Element, depth: usize) -> TResult {
+        match depth.checked_sub(1) {
+            Some(depth) => recurse_inplace(&truncate, root, depth),
+            None => Ok(Element::Error(Error {
+                position: root.get_position().clone(),
+                id: None,
+                message: format!("{} truncated at maximum depth.", root.get_variant_name()),
+            })),
+        }
+    }
+    recurse_inplace(&truncate, root, max_depth).expect("truncating the tree failed!")
+}
+
+/// A tree which is compared and hashed regardless of its positions, e.g. to deduplicate
+/// structurally equal trees parsed from different places of a document.
+///
+/// All positions of the tree are set to the "any" position, which is equal to every position.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct StructuralKey(Element);
+
+// positions are the only fields compared with a wildcard. once they are all "any",
+// every other field is compared strictly, so equality is transitive.
+impl Eq for StructuralKey {}
+
+impl StructuralKey {
+    pub fn new(root: Element) -> Self {
+        fn forget_positions(mut root: Element, _: ()) -> TResult {
+            *root.get_position_mut() = Span::any();
+            for attr in attributes_mut(&mut root) {
+                attr.position = Span::any();
+            }
+            recurse_inplace(&forget_positions, root, ())
+        }
+        StructuralKey(forget_positions(root, ()).expect("forgetting positions failed!"))
+    }
+
+    /// The tree without positions.
+    pub fn element(&self) -> &Element {
+        &self.0
+    }
+
+    pub fn into_element(self) -> Element {
+        self.0
+    }
+}
+
+impl From<Element> for StructuralKey {
+    fn from(root: Element) -> Self {
+        StructuralKey::new(root)
+    }
+}
+
+/// The tag attributes of an element.
+pub(crate) fn attributes_mut(elem: &mut Element) -> Vec<&mut TagAttribute> {
+    match *elem {
+        Element::Table(ref mut e) => e
+            .attributes
+            .iter_mut()
+            .chain(e.caption_attributes.iter_mut())
+            .collect(),
+        Element::TableRow(ref mut e) => e.attributes.iter_mut().collect(),
+        Element::TableCell(ref mut e) => e.attributes.iter_mut().collect(),
+        Element::HtmlTag(ref mut e) => e.attributes.iter_mut().collect(),
+        Element::ExtensionTag(ref mut e) => e.attributes.iter_mut().collect(),
+        Element::Gallery(ref mut e) => e.attributes.iter_mut().collect(),
+        _ => vec![],
+    }
+}
+
+/// The direct children of an element.
+fn children(elem: &Element) -> Vec<&Element> {
+    let lists: Vec<&[Element]> = match *elem {
+        Element::Document(ref e) => vec![&e.content],
+        Element::Formatted(ref e) => vec![&e.content],
+        Element::Paragraph(ref e) => vec![&e.content],
+        Element::ListItem(ref e) => vec![&e.content],
+        Element::List(ref e) => vec![&e.content],
+        Element::TableCell(ref e) => vec![&e.content],
+        Element::HtmlTag(ref e) => vec![&e.content],
+        Element::Gallery(ref e) => vec![&e.content],
+        Element::Figure(ref e) => vec![&e.content, &e.caption],
+        Element::Redirect(ref e) => vec![&e.target],
+        Element::Heading(ref e) => vec![&e.caption, &e.content],
+        Element::Template(ref e) => vec![&e.name, &e.content],
+        Element::TemplateArgument(ref e) => vec![&e.value],
+        Element::Parameter(ref e) => {
+            let mut lists: Vec<&[Element]> = vec![&e.name];
+            lists.extend(e.default.as_deref());
+            lists
+        }
+        Element::InternalReference(ref e) => {
+            let mut lists: Vec<&[Element]> = vec![&e.target];
+            lists.extend(e.options.iter().map(|o| o.as_slice()));
+            lists.push(&e.caption);
+            lists
+        }
+        Element::ExternalReference(ref e) => vec![&e.caption],
+        Element::Table(ref e) => vec![&e.caption, &e.rows],
+        Element::TableRow(ref e) => vec![&e.cells],
+        Element::Text(_)
+        | Element::Comment(_)
+        | Element::ExtensionTag(_)
+        | Element::MagicWord(_)
+        | Element::Variable(_)
+        | Element::Code(_)
+        | Element::HorizontalRule(_)
+        | Element::Error(_) => vec![],
+    };
+    lists.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn structural_keys_ignore_positions() {
+        use std::collections::HashSet;
+
+        let first = parse("''a'' {{b|c=d}} <span class=\"x\">e</span>").unwrap();
+        let moved = parse("\n\n''a'' {{b|c=d}} <span class=\"x\">e</span>").unwrap();
+        assert_ne!(first.get_position(), moved.get_position());
+        let mut set = HashSet::new();
+        set.insert(StructuralKey::new(first.clone()));
+        set.insert(StructuralKey::new(moved));
+        assert_eq!(set.len(), 1);
+        set.insert(StructuralKey::new(parse("''a'' {{b|c=x}}").unwrap()));
+        assert_eq!(set.len(), 2);
+        assert_eq!(
+            set.iter().next().unwrap().element().get_variant_name(),
+            "Document"
+        );
+    }
+
+    #[test]
+    fn structural_keys_compare_heading_markers() {
+        use std::collections::HashSet;
+
+        let root = parse("== a ==\n").unwrap();
+        let two = root.as_document().unwrap().content[0].clone();
+        let mut three = two.as_heading().unwrap().clone();
+        three.close_markers = Some(3);
+        let mut unmarked = three.clone();
+        unmarked.open_markers = None;
+        unmarked.close_markers = None;
+
+        let mut set = HashSet::new();
+        set.insert(StructuralKey::new(two));
+        set.insert(StructuralKey::new(Element::Heading(three)));
+        set.insert(StructuralKey::new(Element::Heading(unmarked)));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn spans_after_splice() {
+        let input = "first ''paragraph''\n\nsecond one\n";
+        let root = parse(input).unwrap();
+        let mut doc = root.as_document().unwrap().clone();
+        let second = doc.content.pop().unwrap();
+        let mut first = doc.content.pop().unwrap().as_paragraph().unwrap().clone();
+        first
+            .content
+            .extend(second.as_paragraph().unwrap().content.iter().cloned());
+        let first_end = first.position.end.offset;
+        doc.content.push(Element::Paragraph(first));
+
+        let root = recompute_spans(Element::Document(doc));
+        let par = &root.as_document().unwrap().content[0];
+        let span = par.get_position();
+        assert!(span.end.offset > first_end);
+        assert_eq!(
+            &input[span.start.offset..span.end.offset],
+            "first ''paragraph''\n\nsecond one"
+        );
+        let italic = par.as_paragraph().unwrap().content[1].get_position();
+        assert_eq!(
+            &input[italic.start.offset..italic.end.offset],
+            "''paragraph''"
+        );
+        let doc = root.get_position();
+        assert_eq!((doc.start.offset, doc.end.offset), (0, input.len()));
+    }
+}
@@ -1,13 +1,13 @@
 //! Helper trait for operations reading from the document tree.
 
-use super::ast::Element;
+use super::ast::{Element, TagAttribute};
 use std::io;
 
 /// Implements a traversion over a tree of `Element`.
 ///
 /// All fields of the traversion struct can be mutated,
 /// external settings cannot.
-pub trait Traversion<'a, S: Copy + ?Sized> {
+pub trait Traversion<'a, S: Copy> {
     /// push to the traversion path.
     fn path_push(&mut self, elem: &'a Element);
     /// pop from the traversion path.
@@ -18,7 +18,12 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
     /// if the result is `false`, handling is complete and
     /// children of this node are not considered,
     /// otherwise `work()` is recursively called for all children.
-    fn work(&mut self, _root: &'a Element, _settings: S, _out: &mut io::Write) -> io::Result<bool> {
+    fn work(
+        &mut self,
+        _root: &'a Element,
+        _settings: S,
+        _out: &mut dyn io::Write,
+    ) -> io::Result<bool> {
         Ok(true)
     }
 
@@ -30,7 +35,7 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
         &mut self,
         _root: &'a [Element],
         _settings: S,
-        _out: &mut io::Write,
+        _out: &mut dyn io::Write,
     ) -> io::Result<bool> {
         Ok(true)
     }
@@ -40,22 +45,36 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
         &mut self,
         content: &'a [Element],
         settings: S,
-        out: &mut io::Write,
+        out: &mut dyn io::Write,
     ) -> io::Result<()> {
         if !self.work_vec(content, settings, out)? {
             return Ok(());
         }
-        for elem in &content[..] {
+        for elem in content {
             self.run(elem, settings, out)?;
         }
         Ok(())
     }
+    /// run this traversion for the elements of attribute values, like templates
+    /// providing table attributes.
+    fn run_attributes(
+        &mut self,
+        attributes: &'a [TagAttribute],
+        settings: S,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        for attr in attributes {
+            self.run_vec(&attr.value_elements, settings, out)?;
+        }
+        Ok(())
+    }
     /// run this traversion for an element.
-    fn run(&mut self, root: &'a Element, settings: S, out: &mut io::Write) -> io::Result<()> {
+    fn run(&mut self, root: &'a Element, settings: S, out: &mut dyn io::Write) -> io::Result<()> {
         self.path_push(root);
 
         // break if work function breaks recursion.
         if !self.work(root, settings, out)? {
+            self.path_pop();
             return Ok(());
         }
         match *root {
@@ -64,9 +83,17 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
             Element::Paragraph(ref e) => self.run_vec(&e.content, settings, out)?,
             Element::ListItem(ref e) => self.run_vec(&e.content, settings, out)?,
             Element::List(ref e) => self.run_vec(&e.content, settings, out)?,
-            Element::TableCell(ref e) => self.run_vec(&e.content, settings, out)?,
+            Element::TableCell(ref e) => {
+                self.run_attributes(&e.attributes, settings, out)?;
+                self.run_vec(&e.content, settings, out)?;
+            }
             Element::HtmlTag(ref e) => self.run_vec(&e.content, settings, out)?,
             Element::Gallery(ref e) => self.run_vec(&e.content, settings, out)?,
+            Element::Figure(ref e) => {
+                self.run_vec(&e.content, settings, out)?;
+                self.run_vec(&e.caption, settings, out)?;
+            }
+            Element::Redirect(ref e) => self.run_vec(&e.target, settings, out)?,
             Element::Heading(ref e) => {
                 self.run_vec(&e.caption, settings, out)?;
                 self.run_vec(&e.content, settings, out)?;
@@ -76,6 +103,12 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
                 self.run_vec(&e.content, settings, out)?;
             }
             Element::TemplateArgument(ref e) => self.run_vec(&e.value, settings, out)?,
+            Element::Parameter(ref e) => {
+                self.run_vec(&e.name, settings, out)?;
+                if let Some(ref default) = e.default {
+                    self.run_vec(default, settings, out)?;
+                }
+            }
             Element::InternalReference(ref e) => {
                 self.run_vec(&e.target, settings, out)?;
                 for option in &e.options {
@@ -85,11 +118,23 @@ pub trait Traversion<'a, S: Copy + ?Sized> {
             }
             Element::ExternalReference(ref e) => self.run_vec(&e.caption, settings, out)?,
             Element::Table(ref e) => {
+                self.run_attributes(&e.attributes, settings, out)?;
+                self.run_attributes(&e.caption_attributes, settings, out)?;
                 self.run_vec(&e.caption, settings, out)?;
                 self.run_vec(&e.rows, settings, out)?;
             }
-            Element::TableRow(ref e) => self.run_vec(&e.cells, settings, out)?,
-            Element::Text(_) | Element::Comment(_) | Element::Error(_) => (),
+            Element::TableRow(ref e) => {
+                self.run_attributes(&e.attributes, settings, out)?;
+                self.run_vec(&e.cells, settings, out)?;
+            }
+            Element::Text(_)
+            | Element::Comment(_)
+            | Element::ExtensionTag(_)
+            | Element::MagicWord(_)
+            | Element::Variable(_)
+            | Element::Code(_)
+            | Element::HorizontalRule(_)
+            | Element::Error(_) => (),
         }
         self.path_pop();
         Ok(())
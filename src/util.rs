@@ -1,6 +1,8 @@
 //! Utility functions and types
 
 use crate::ast;
+use std::borrow::Cow;
+use std::sync::OnceLock;
 
 /// The terminal width.
 const TERMINAL_WIDTH: usize = 80;
@@ -14,7 +16,7 @@ pub fn combine<T>(t: (Vec<T>, Vec<T>)) -> Vec<T> {
 /// Compiles a list of start and end positions of the input source lines.
 ///
 /// This representation is used to calculate line and column position from the input offset.
-pub fn get_source_lines(source: &str) -> Vec<ast::SourceLine> {
+pub fn get_source_lines(source: &str) -> Vec<ast::SourceLine<'_>> {
     let mut pos = 0;
     let mut result = Vec::new();
 
@@ -29,17 +31,67 @@ pub fn get_source_lines(source: &str) -> Vec<ast::SourceLine> {
     result
 }
 
+/// Which braces opened in the input are closed, computed for all positions of the
+/// input at once, when it is first needed.
+#[derive(Debug, Default)]
+pub struct ClosingBraces {
+    closed: OnceLock<Vec<bool>>,
+}
+
+impl ClosingBraces {
+    /// Tests if the braces opened right before `pos` are closed, counting nested pairs
+    /// of braces.
+    pub fn has_closing_braces(&self, pos: usize, source_lines: &[ast::SourceLine]) -> bool {
+        let closed = self.closed.get_or_init(|| closing_braces(source_lines));
+        closed.get(pos).copied().unwrap_or(false)
+    }
+}
+
+/// For every position, tests if a scan starting there with one open pair of braces
+/// reaches the matching `}}`. Pairs are read from left to right, like `{{` and `}}`
+/// in `{{{}}}`.
+///
+/// Scans from different positions continue alike once they reach the same position,
+/// so the depth they reach is computed backwards from the end of the input:
+/// `depth[i]` is the change of depth from `i` to the end, and `max[i]` the largest
+/// such change at any position the scan from `i` passes. The braces are closed if
+/// the scan gets one pair shallower than it started.
+fn closing_braces(source_lines: &[ast::SourceLine]) -> Vec<bool> {
+    let mut text = Vec::new();
+    for line in source_lines {
+        text.extend_from_slice(line.content.as_bytes());
+        text.push(b'\n');
+    }
+    let len = text.len();
+    let mut depth = vec![0i64; len + 2];
+    let mut max = vec![0i64; len + 2];
+    let mut closed = vec![false; len];
+    for i in (0..len).rev() {
+        let (change, next) = match (text[i], text.get(i + 1)) {
+            (b'{', Some(b'{')) => (1, i + 2),
+            (b'}', Some(b'}')) => (-1, i + 2),
+            _ => (0, i + 1),
+        };
+        depth[i] = change + depth[next];
+        max[i] = depth[i].max(max[next]);
+        closed[i] = max[i] > depth[i];
+    }
+    closed
+}
+
 /// Tests if a string is entirely whitespace
 pub fn is_whitespace(input: &str) -> bool {
     input.chars().all(|c| c.is_whitespace())
 }
 
 /// Shorten a string to fit into `TERMINAL_WIDTH`.
-pub fn shorten_str(input: &str) -> String {
+///
+/// The input is only copied if it actually needs to be shortened.
+pub fn shorten_str(input: &str) -> Cow<'_, str> {
     let input_len = input.chars().count();
 
-    if input.len() < TERMINAL_WIDTH {
-        return String::from(input);
+    if input_len < TERMINAL_WIDTH {
+        return Cow::Borrowed(input);
     }
 
     let filler = " .. ";
@@ -57,13 +109,87 @@ pub fn shorten_str(input: &str) -> String {
             result.push(c);
         }
     }
-    result
+    Cow::Owned(result)
+}
+
+/// Shorten a string to fit into `TERMINAL_WIDTH`, keeping the text
+/// around the character column `col` (starting at 1) visible.
+///
+/// The input is only copied if it actually needs to be shortened.
+pub fn shorten_str_around(input: &str, col: usize) -> Cow<'_, str> {
+    let input_len = input.chars().count();
+
+    if input_len < TERMINAL_WIDTH {
+        return Cow::Borrowed(input);
+    }
+
+    let filler = " .. ";
+    let window = TERMINAL_WIDTH - 2 * filler.chars().count();
+    let center = col.saturating_sub(1);
+    let start = center.saturating_sub(window / 2).min(input_len - window);
+
+    let mut result = String::new();
+    if start > 0 {
+        result.push_str(filler);
+    }
+    result.extend(input.chars().skip(start).take(window));
+    if start + window < input_len {
+        result.push_str(filler);
+    }
+    Cow::Owned(result)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_closing_braces() {
+        // scan for the braces closing the ones opened right before `pos`.
+        fn scan(input: &str, pos: usize) -> bool {
+            let input = input.as_bytes();
+            let mut depth = 1;
+            let mut i = pos;
+            while i < input.len() {
+                if input[i..].starts_with(b"{{") {
+                    depth += 1;
+                    i += 2;
+                } else if input[i..].starts_with(b"}}") {
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+            }
+            false
+        }
+
+        let inputs = [
+            "{{a}}",
+            "{{a",
+            "x {{a {{b}} c\n}} {{d",
+            "{{{1}}} {{{{a}}}} }",
+            "{{a}\n}b}} {{",
+            "{{ä|{{ö}}ü}}",
+        ];
+        for input in &inputs {
+            let source_lines = get_source_lines(input);
+            let braces = ClosingBraces::default();
+            for pos in 0..=input.len() {
+                assert_eq!(
+                    braces.has_closing_braces(pos, &source_lines),
+                    scan(input, pos),
+                    "{:?} at {}",
+                    input,
+                    pos
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_is_whitespace() {
         for arg in &["", "   ", "\t", "\n", "\t\t\t", "\n\t "] {
@@ -74,4 +200,45 @@ mod tests {
             assert!(!is_whitespace(arg), "!is_whitespace({:?})", arg);
         }
     }
+
+    #[test]
+    fn test_shorten_str() {
+        let short = "short ascii text";
+        assert!(matches!(shorten_str(short), Cow::Borrowed(s) if s == short));
+
+        let long = "a".repeat(200);
+        let shortened = shorten_str(&long);
+        assert!(matches!(shortened, Cow::Owned(_)));
+        assert_eq!(shortened.chars().count(), TERMINAL_WIDTH);
+        assert!(shortened.contains(" .. "));
+
+        // 60 characters, but more than `TERMINAL_WIDTH` bytes.
+        let multibyte = "äöü".repeat(20);
+        assert!(multibyte.len() > TERMINAL_WIDTH);
+        assert!(matches!(shorten_str(&multibyte), Cow::Borrowed(_)));
+
+        let long_multibyte = "ʕ•ᴥ•ʔ".repeat(40);
+        let shortened = shorten_str(&long_multibyte);
+        assert_eq!(shortened.chars().count(), TERMINAL_WIDTH);
+        assert!(shortened.starts_with("ʕ•ᴥ•ʔ"));
+    }
+
+    #[test]
+    fn test_shorten_str_around() {
+        let short = "short ascii text";
+        assert!(matches!(shorten_str_around(short, 3), Cow::Borrowed(s) if s == short));
+
+        let long = format!("{}error{}", "ä".repeat(190), "ö".repeat(5));
+        let shortened = shorten_str_around(&long, 191);
+        assert!(shortened.chars().count() <= TERMINAL_WIDTH);
+        assert!(shortened.starts_with(" .. ä"));
+        assert!(shortened.ends_with("errorööööö"));
+
+        let shortened = shorten_str_around(&long, 1);
+        assert!(shortened.starts_with("ää"));
+        assert!(shortened.ends_with(" .. "));
+
+        // columns beyond the line end show the end of the line.
+        assert!(shorten_str_around(&long, 500).ends_with('ö'));
+    }
 }
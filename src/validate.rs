@@ -0,0 +1,177 @@
+//! Checks for structural invariants of the document tree,
+//! e.g. to find bugs in custom transformations.
+
+use crate::ast::*;
+use crate::traversion::Traversion;
+use serde_derive::{Deserialize, Serialize};
+use std::io;
+
+/// An element violating a structural invariant.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct StructuralViolation {
+    /// Position of the offending element.
+    pub position: Span,
+    /// Variant name of the offending element.
+    pub element: String,
+    /// Description of the violated invariant.
+    pub message: String,
+}
+
+/// Collects violations of structural invariants.
+struct Validator<'e> {
+    path: Vec<&'e Element>,
+    violations: Vec<StructuralViolation>,
+}
+
+impl<'e> Validator<'e> {
+    fn violation(&mut self, elem: &Element, message: String) {
+        self.violations.push(StructuralViolation {
+            position: elem.get_position().clone(),
+            element: elem.get_variant_name().to_string(),
+            message,
+        });
+    }
+
+    /// Check that all children of a container are of the expected variant.
+    fn expect_children(&mut self, parent: &str, children: &[Element], expected: &str) {
+        for child in children {
+            if child.get_variant_name() != expected {
+                let message = format!("{} may only contain {} elements.", parent, expected);
+                self.violation(child, message);
+            }
+        }
+    }
+}
+
+impl<'e> Traversion<'e, ()> for Validator<'e> {
+    fn path_push(&mut self, elem: &'e Element) {
+        self.path.push(elem);
+    }
+    fn path_pop(&mut self) -> Option<&'e Element> {
+        self.path.pop()
+    }
+    fn get_path(&self) -> &Vec<&'e Element> {
+        &self.path
+    }
+
+    fn work(&mut self, root: &'e Element, _: (), _: &mut dyn io::Write) -> io::Result<bool> {
+        match *root {
+            Element::List(ref list) => self.expect_children("List", &list.content, "ListItem"),
+            Element::Table(ref table) => self.expect_children("Table", &table.rows, "TableRow"),
+            Element::TableRow(ref row) => self.expect_children("TableRow", &row.cells, "TableCell"),
+            Element::Template(ref template) => {
+                self.expect_children("Template", &template.content, "TemplateArgument")
+            }
+            Element::Heading(ref heading) => {
+                if heading.depth == 0 {
+                    self.violation(root, "heading depth must be at least 1.".to_string());
+                }
+                for child in &heading.content {
+                    if let Element::Heading(ref sub) = *child {
+                        if sub.depth <= heading.depth {
+                            let message = format!(
+                                "heading of depth {} is nested in a heading of depth {}.",
+                                sub.depth, heading.depth
+                            );
+                            self.violation(child, message);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        };
+        Ok(true)
+    }
+}
+
+/// Check structural invariants of a document tree, like "a `List` contains only `ListItem`s"
+/// or "nested headings are deeper than their parent", and return all violations.
+pub fn validate(root: &Element) -> Vec<StructuralViolation> {
+    let mut validator = Validator {
+        path: vec![],
+        violations: vec![],
+    };
+    validator
+        .run(root, (), &mut io::sink())
+        .expect("writing to a sink failed!");
+    validator.violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn parsed_tree_is_valid() {
+        let input = "== A ==\n=== B ===\n* item\n{|\n| cell\n|}\n{{t|arg}}\n";
+        assert_eq!(validate(&parse(input).unwrap()), vec![]);
+    }
+
+    #[test]
+    fn unterminated_table_is_valid() {
+        assert_eq!(validate(&parse("{|\n| a").unwrap()), vec![]);
+    }
+
+    #[test]
+    fn parsed_edge_cases_are_valid() {
+        let inputs = [
+            "{|\n| a\n{|\n| b\n",
+            "{|\n|+ caption\n| a\n|+ late caption\n| b",
+            "<ul><ul><li>b</li></ul></ul>\n",
+            "<ol><li>a<ul><li>b</li></ul></li>\n</ol>\n",
+            "* a\n** b\n*: c\n",
+            "=== a ===\n== b ==\n= c\n",
+        ];
+        for input in &inputs {
+            assert_eq!(validate(&parse(input).unwrap()), vec![], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn invalid_tree() {
+        let text = Element::Text(Text {
+            position: Span::any(),
+            id: None,
+            text: "loose".into(),
+        });
+        let nested = Element::Heading(Heading {
+            depth: 2,
+            ..Default::default()
+        });
+        let root = Element::Document(Document {
+            position: Span::any(),
+            id: None,
+            content: vec![
+                Element::List(List {
+                    position: Span::any(),
+                    id: None,
+                    content: vec![text.clone(), Element::ListItem(ListItem::default())],
+                }),
+                Element::Heading(Heading {
+                    depth: 3,
+                    content: vec![nested, text],
+                    ..Default::default()
+                }),
+            ],
+        });
+
+        let violations: Vec<_> = validate(&root)
+            .into_iter()
+            .map(|v| (v.element, v.message))
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                (
+                    "Text".to_string(),
+                    "List may only contain ListItem elements.".to_string()
+                ),
+                (
+                    "Heading".to_string(),
+                    "heading of depth 2 is nested in a heading of depth 3.".to_string()
+                ),
+            ]
+        );
+    }
+}